@@ -0,0 +1,40 @@
+-- Tables of the IMDb datasets, see https://developer.imdb.com/non-commercial-datasets/
+-- Column names are the lower-case variants of the TSV headers.
+-- Array-valued columns (genres, primaryprofession, knownfortitles) hold comma-separated lists.
+
+CREATE TABLE IF NOT EXISTS title_basics (
+    tconst          text PRIMARY KEY,
+    titletype       text,
+    primarytitle    text,
+    originaltitle   text,
+    isadult         boolean NOT NULL DEFAULT false,
+    startyear       integer,
+    endyear         integer,
+    runtimeminutes  integer,
+    genres          text
+);
+
+CREATE TABLE IF NOT EXISTS title_ratings (
+    tconst          text PRIMARY KEY,
+    averagerating   double precision,
+    numvotes        integer
+);
+
+CREATE TABLE IF NOT EXISTS title_principals (
+    tconst          text NOT NULL,
+    ordering        integer NOT NULL,
+    nconst          text NOT NULL,
+    category        text,
+    job             text,
+    characters      text,
+    PRIMARY KEY (tconst, ordering)
+);
+
+CREATE TABLE IF NOT EXISTS name_basics (
+    nconst              text PRIMARY KEY,
+    primaryname         text,
+    birthyear           integer,
+    deathyear           integer,
+    primaryprofession   text,
+    knownfortitles      text
+);
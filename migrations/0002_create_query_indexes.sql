@@ -0,0 +1,13 @@
+-- Indexes used by the queries in repo.rs
+
+-- title_to_names, and the principals of a contributor
+CREATE INDEX IF NOT EXISTS title_principals_nconst_idx ON title_principals (nconst);
+
+-- the memory cache is loaded by category
+CREATE INDEX IF NOT EXISTS title_principals_category_idx ON title_principals (category);
+
+-- exact name match and wildcard search with a fixed prefix
+CREATE INDEX IF NOT EXISTS name_basics_primaryname_idx ON name_basics (primaryname text_pattern_ops);
+
+-- title search is restricted by title type
+CREATE INDEX IF NOT EXISTS title_basics_titletype_idx ON title_basics (titletype);
@@ -0,0 +1,7 @@
+-- Each run of the import subcommand, identifying the loaded dataset for cache snapshots
+
+CREATE TABLE IF NOT EXISTS dataset_imports (
+    id              serial PRIMARY KEY,
+    imported_at     timestamptz NOT NULL DEFAULT now(),
+    datasets        text NOT NULL
+);
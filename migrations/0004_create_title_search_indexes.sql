@@ -0,0 +1,11 @@
+-- Indexes of the title search, see titles_by_name in repo.rs
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- fuzzy search by trigram similarity, these also serve the ilike substring search
+CREATE INDEX IF NOT EXISTS title_basics_primarytitle_trgm_idx ON title_basics USING gin (primarytitle gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS title_basics_originaltitle_trgm_idx ON title_basics USING gin (originaltitle gin_trgm_ops);
+
+-- fuzzy search by title words
+CREATE INDEX IF NOT EXISTS title_basics_fulltext_idx ON title_basics
+    USING gin (to_tsvector('simple', coalesce(primarytitle, '') || ' ' || coalesce(originaltitle, '')));
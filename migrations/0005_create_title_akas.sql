@@ -0,0 +1,20 @@
+-- Alternate titles of title.akas, searched by titles_by_name in repo.rs.
+-- types and attributes hold the IMDb values as they are.
+
+CREATE TABLE IF NOT EXISTS title_akas (
+    titleid         text NOT NULL,
+    ordering        integer NOT NULL,
+    title           text,
+    region          text,
+    language        text,
+    types           text,
+    attributes      text,
+    isoriginaltitle boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (titleid, ordering)
+);
+
+-- substring and fuzzy search by trigram similarity
+CREATE INDEX IF NOT EXISTS title_akas_title_trgm_idx ON title_akas USING gin (title gin_trgm_ops);
+
+-- fuzzy search by title words
+CREATE INDEX IF NOT EXISTS title_akas_fulltext_idx ON title_akas USING gin (to_tsvector('simple', coalesce(title, '')));
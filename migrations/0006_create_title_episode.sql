@@ -0,0 +1,11 @@
+-- Episodes of title.episode, with their series (parenttconst)
+
+CREATE TABLE IF NOT EXISTS title_episode (
+    tconst          text PRIMARY KEY,
+    parenttconst    text NOT NULL,
+    seasonnumber    integer,
+    episodenumber   integer
+);
+
+-- the episodes of a series
+CREATE INDEX IF NOT EXISTS title_episode_parenttconst_idx ON title_episode (parenttconst);
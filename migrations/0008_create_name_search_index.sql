@@ -0,0 +1,10 @@
+-- Names match ignoring case, accents and repeated whitespace, e.g. "penelope  cruz" matches "Penélope Cruz".
+-- unaccent() depends on the search path, so it is wrapped with a qualified dictionary in an immutable function for the index.
+
+CREATE EXTENSION IF NOT EXISTS unaccent SCHEMA public;
+
+CREATE OR REPLACE FUNCTION imdb_searchname(name text) RETURNS text
+    LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE
+    AS $$ SELECT lower(regexp_replace(btrim(public.unaccent('public.unaccent'::regdictionary, name)), '\s+', ' ', 'g')) $$;
+
+CREATE INDEX IF NOT EXISTS name_basics_searchname_idx ON name_basics (imdb_searchname(primaryname));
@@ -0,0 +1,50 @@
+-- The tables and indexes of the PostgreSQL migrations, for the SQLite repository.
+-- Array-valued columns (genres, primaryprofession, knownfortitles) hold comma-separated lists.
+
+CREATE TABLE IF NOT EXISTS title_basics (
+    tconst          text PRIMARY KEY,
+    titletype       text,
+    primarytitle    text,
+    originaltitle   text,
+    isadult         boolean NOT NULL DEFAULT 0,
+    startyear       integer,
+    endyear         integer,
+    runtimeminutes  integer,
+    genres          text
+);
+
+CREATE TABLE IF NOT EXISTS title_ratings (
+    tconst          text PRIMARY KEY,
+    averagerating   real,
+    numvotes        integer
+);
+
+CREATE TABLE IF NOT EXISTS title_principals (
+    tconst          text NOT NULL,
+    ordering        integer NOT NULL,
+    nconst          text NOT NULL,
+    category        text,
+    job             text,
+    characters      text,
+    PRIMARY KEY (tconst, ordering)
+);
+
+CREATE TABLE IF NOT EXISTS name_basics (
+    nconst              text PRIMARY KEY,
+    primaryname         text,
+    birthyear           integer,
+    deathyear           integer,
+    primaryprofession   text,
+    knownfortitles      text
+);
+
+CREATE TABLE IF NOT EXISTS dataset_imports (
+    id              integer PRIMARY KEY AUTOINCREMENT,
+    imported_at     text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    datasets        text NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS title_principals_nconst_idx ON title_principals (nconst);
+CREATE INDEX IF NOT EXISTS title_principals_category_idx ON title_principals (category);
+CREATE INDEX IF NOT EXISTS name_basics_primaryname_idx ON name_basics (primaryname);
+CREATE INDEX IF NOT EXISTS title_basics_titletype_idx ON title_basics (titletype);
@@ -0,0 +1,13 @@
+-- Alternate titles of title.akas, see migrations/0005_create_title_akas.sql
+
+CREATE TABLE IF NOT EXISTS title_akas (
+    titleid         text NOT NULL,
+    ordering        integer NOT NULL,
+    title           text,
+    region          text,
+    language        text,
+    types           text,
+    attributes      text,
+    isoriginaltitle boolean NOT NULL DEFAULT 0,
+    PRIMARY KEY (titleid, ordering)
+);
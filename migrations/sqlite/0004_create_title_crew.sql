@@ -0,0 +1,7 @@
+-- Directors and writers of title.crew, as comma-separated lists of nconsts
+
+CREATE TABLE IF NOT EXISTS title_crew (
+    tconst      text PRIMARY KEY,
+    directors   text,
+    writers     text
+);
@@ -0,0 +1,6 @@
+-- Names match ignoring case, accents and repeated whitespace, e.g. "penelope  cruz" matches "Penélope Cruz".
+-- SQLite cannot remove accents, the search names are filled in by the application.
+
+ALTER TABLE name_basics ADD COLUMN searchname text;
+
+CREATE INDEX IF NOT EXISTS name_basics_searchname_idx ON name_basics (searchname);
@@ -0,0 +1,233 @@
+use std::fmt;
+
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::okapi::schemars::{self, JsonSchema};
+use rocket_okapi::response::OpenApiResponderInner;
+use rocket_okapi::util::add_schema_response;
+
+use crate::kevinbacon::{NameNotFound, SearchError};
+use crate::schemas::{NameBasics, SearchStats};
+
+/// The errors of the API handlers, answered with an `ErrorBody` and the matching HTTP status
+#[derive(Debug)]
+pub enum ApiError {
+    /// A title or name that does not exist, identified by the id or name that was asked for
+    NotFound { id: String, message: String },
+    /// An invalid request parameter, identified by its value if there is one
+    BadInput { id: Option<String>, message: String },
+    /// A failed database query
+    Database { message: String },
+    /// A query or search that did not complete in time, with how far a search got
+    Timeout {
+        message: String,
+        stats: Option<SearchStats>,
+    },
+    /// A name of several contributors, when the request does not let the busiest be chosen
+    Ambiguous {
+        id: String,
+        message: String,
+        candidates: Vec<NameBasics>,
+    },
+}
+
+/// The JSON body of error responses
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorBody {
+    /// not_found, bad_input, database, timeout or ambiguous
+    pub code: String,
+    pub message: String,
+    /// the id, name or parameter value the error is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// the contributors an ambiguous name stands for, the busiest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<NameBasics>,
+    /// how far a search that timed out got
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<SearchStats>,
+}
+
+impl ApiError {
+    pub fn not_found(id: &str, message: String) -> ApiError {
+        ApiError::NotFound {
+            id: id.to_string(),
+            message,
+        }
+    }
+
+    pub fn bad_input(id: Option<&str>, message: String) -> ApiError {
+        ApiError::BadInput {
+            id: id.map(|id| id.to_string()),
+            message,
+        }
+    }
+
+    /// A failed query, described by `context`. Running out of pooled connections is a timeout.
+    pub fn database(context: String, err: sqlx::Error) -> ApiError {
+        println!("{}: {}", context, err);
+        match err {
+            sqlx::Error::PoolTimedOut => ApiError::Timeout {
+                message: format!("{}: the database is busy", context),
+                stats: None,
+            },
+            err => ApiError::Database {
+                message: format!("{}: {}", context, err),
+            },
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::NotFound { .. } => Status::NotFound,
+            ApiError::BadInput { .. } => Status::BadRequest,
+            ApiError::Database { .. } => Status::InternalServerError,
+            ApiError::Timeout { .. } => Status::GatewayTimeout,
+            ApiError::Ambiguous { .. } => Status::Conflict,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (code, message, id) = match self {
+            ApiError::NotFound { id, message } => ("not_found", message, Some(id.clone())),
+            ApiError::BadInput { id, message } => ("bad_input", message, id.clone()),
+            ApiError::Database { message } => ("database", message, None),
+            ApiError::Timeout { message, .. } => ("timeout", message, None),
+            ApiError::Ambiguous { id, message, .. } => ("ambiguous", message, Some(id.clone())),
+        };
+        let candidates = match self {
+            ApiError::Ambiguous { candidates, .. } => candidates.clone(),
+            _ => vec![],
+        };
+        let stats = match self {
+            ApiError::Timeout { stats, .. } => stats.clone(),
+            _ => None,
+        };
+        ErrorBody {
+            code: code.to_string(),
+            message: message.clone(),
+            id,
+            candidates,
+            stats,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body().message)
+    }
+}
+
+impl From<NameNotFound> for ApiError {
+    fn from(err: NameNotFound) -> ApiError {
+        let message = format!("Could not find actor with ID {}", err.0);
+        ApiError::NotFound { id: err.0, message }
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(err: SearchError) -> ApiError {
+        match err {
+            SearchError::NameNotFound(err) => ApiError::from(err),
+            SearchError::OutOfBudget(stats) => ApiError::Timeout {
+                message: format!(
+                    "The search gave up after {} degrees and {} names in {:?}",
+                    stats.levels, stats.names_visited, stats.elapsed
+                ),
+                stats: Some(stats),
+            },
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.body()).respond_to(request)?)
+            .status(self.status())
+            .ok()
+    }
+}
+
+/// Unknown routes are answered like unknown ids
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    ApiError::not_found(
+        request.uri().path().as_str(),
+        format!("Could not find {}", request.uri().path()),
+    )
+}
+
+/// Missing or unparsable query parameters
+#[catch(422)]
+pub fn unprocessable(request: &Request) -> ApiError {
+    ApiError::bad_input(
+        request.uri().query().map(|query| query.as_str()),
+        "Missing or invalid query parameters".to_string(),
+    )
+}
+
+impl OpenApiResponderInner for ApiError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<ErrorBody>();
+        for status in [400, 404, 409, 500, 504] {
+            add_schema_response(&mut responses, status, "application/json", schema.clone())?;
+        }
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::serde::json;
+    use rocket_db_pools::sqlx;
+
+    use crate::error::ApiError;
+    use crate::kevinbacon::NameNotFound;
+
+    #[test]
+    fn error_bodies() {
+        let err = ApiError::from(NameNotFound("nm0000001".to_string()));
+        assert_eq!(err.status(), Status::NotFound);
+        assert_eq!(
+            json::to_string(&err.body()).unwrap(),
+            r#"{"code":"not_found","message":"Could not find actor with ID nm0000001","id":"nm0000001"}"#
+        );
+
+        let err = ApiError::bad_input(None, "Unknown genre Foo".to_string());
+        assert_eq!(err.status(), Status::BadRequest);
+        assert_eq!(
+            json::to_string(&err.body()).unwrap(),
+            r#"{"code":"bad_input","message":"Unknown genre Foo"}"#
+        );
+
+        let err = ApiError::Ambiguous {
+            id: "Robin Wright".to_string(),
+            message: "2 contributors are named Robin Wright".to_string(),
+            candidates: vec![],
+        };
+        assert_eq!(err.status(), Status::Conflict);
+        assert_eq!(err.body().code, "ambiguous");
+        assert_eq!(err.body().id.as_deref(), Some("Robin Wright"));
+    }
+
+    #[test]
+    fn database_errors() {
+        let err = ApiError::database("Error searching".to_string(), sqlx::Error::RowNotFound);
+        assert_eq!(err.status(), Status::InternalServerError);
+        assert_eq!(err.body().code, "database");
+
+        let err = ApiError::database("Error searching".to_string(), sqlx::Error::PoolTimedOut);
+        assert_eq!(err.status(), Status::GatewayTimeout);
+        assert_eq!(err.body().code, "timeout");
+    }
+}
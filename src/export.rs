@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use rocket::http::ContentType;
+use rocket::FromFormField;
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+
+use crate::error::ApiError;
+use crate::graph::{ActorGraph, TitleFilter};
+use crate::repo::Repository;
+use crate::schemas::TitleBasics;
+
+/// Number of names or titles written per chunk, their attributes are retrieved together
+const CHUNK_SIZE: u32 = 10_000;
+
+/// The file format of a graph export
+#[derive(Clone, Copy, Debug, Default, PartialEq, FromFormField, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// GraphML, read by networkx and Gephi
+    #[default]
+    GraphMl,
+    /// GEXF 1.3, the format of Gephi
+    Gexf,
+    /// Graphviz DOT
+    Dot,
+    /// a CSV list of edges, without node attributes
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Option<ExportFormat> {
+        match format.to_lowercase().as_str() {
+            "graphml" => Some(ExportFormat::GraphMl),
+            "gexf" => Some(ExportFormat::Gexf),
+            "dot" => Some(ExportFormat::Dot),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            ExportFormat::GraphMl => ContentType::new("application", "graphml+xml"),
+            ExportFormat::Gexf => ContentType::XML,
+            ExportFormat::Dot => ContentType::new("text", "vnd.graphviz"),
+            ExportFormat::Csv => ContentType::CSV,
+        }
+    }
+}
+
+/// The graph exported from the actor graph
+#[derive(Clone, Copy, Debug, Default, PartialEq, FromFormField, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphKind {
+    /// names and titles are nodes, edges connect names to their titles
+    #[default]
+    Bipartite,
+    /// names are nodes, edges connect names sharing titles and are weighted by the number of titles
+    Projected,
+}
+
+impl GraphKind {
+    pub fn parse(kind: &str) -> Option<GraphKind> {
+        match kind.to_lowercase().as_str() {
+            "bipartite" => Some(GraphKind::Bipartite),
+            "projected" => Some(GraphKind::Projected),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Part {
+    Header,
+    Names,
+    Titles,
+    Edges,
+    Footer,
+    Done,
+}
+
+/// A node with its attributes from name_basics or title_basics
+struct Node<'a> {
+    id: &'a str,
+    kind: &'static str,
+    label: Option<&'a str>,
+    titletype: Option<&'a str>,
+    startyear: Option<i32>,
+}
+
+/// The ids of nodes of an export, names and titles
+#[derive(Debug, Default)]
+pub struct NodeIds {
+    pub nconsts: Vec<String>,
+    pub tconsts: Vec<String>,
+}
+
+impl NodeIds {
+    pub fn len(&self) -> usize {
+        self.nconsts.len() + self.tconsts.len()
+    }
+}
+
+/// The attributes of nodes from name_basics and title_basics, retrieved before they are written
+#[derive(Debug, Default)]
+pub struct NodeAttributes {
+    names: HashMap<String, String>,
+    titles: HashMap<String, TitleBasics>,
+}
+
+impl NodeAttributes {
+    /// Retrieve the attributes of the nodes, `CHUNK_SIZE` ids per query
+    pub async fn fetch(
+        repository: &Repository,
+        nodes: &NodeIds,
+    ) -> Result<NodeAttributes, ApiError> {
+        let mut attributes = NodeAttributes::default();
+        for nconsts in nodes.nconsts.chunks(CHUNK_SIZE as usize) {
+            attributes
+                .names
+                .extend(repository.primary_names(nconsts).await?);
+        }
+        for tconsts in nodes.tconsts.chunks(CHUNK_SIZE as usize) {
+            attributes
+                .titles
+                .extend(repository.title_basics(tconsts).await?);
+        }
+        Ok(attributes)
+    }
+}
+
+/// Writes a graph in chunks, so it can be streamed without holding the whole file.
+/// Names with a role accepted by the filter are exported, with the titles accepted by the filter.
+/// Nodes and edges are written in id order.
+pub struct GraphExport {
+    graph: Arc<ActorGraph>,
+    filter: TitleFilter,
+    format: ExportFormat,
+    kind: GraphKind,
+    part: Part,
+    /// the first name or title of the next chunk
+    next: u32,
+    edge_count: u64,
+}
+
+impl GraphExport {
+    pub fn new(
+        graph: Arc<ActorGraph>,
+        filter: TitleFilter,
+        format: ExportFormat,
+        kind: GraphKind,
+    ) -> GraphExport {
+        GraphExport {
+            graph,
+            filter,
+            format,
+            kind,
+            part: Part::Header,
+            next: 0,
+            edge_count: 0,
+        }
+    }
+
+    /// The nodes of the whole export
+    pub fn nodes(&self) -> NodeIds {
+        NodeIds {
+            nconsts: self.names_in(0..self.graph.name_count() as u32),
+            tconsts: match self.kind {
+                GraphKind::Bipartite => self.titles_in(0..self.graph.title_count() as u32),
+                GraphKind::Projected => vec![],
+            },
+        }
+    }
+
+    /// The nodes written by the next chunk, whose attributes it needs
+    pub fn next_nodes(&self) -> NodeIds {
+        match self.part {
+            Part::Names => NodeIds {
+                nconsts: self.names_in(self.chunk(self.graph.name_count())),
+                tconsts: vec![],
+            },
+            Part::Titles => NodeIds {
+                nconsts: vec![],
+                tconsts: self.titles_in(self.chunk(self.graph.title_count())),
+            },
+            _ => NodeIds::default(),
+        }
+    }
+
+    /// The next part of the file, `None` once it is complete.
+    /// The attributes of its nodes are taken from `attributes`, nodes without them are written without a label.
+    pub fn next_chunk(&mut self, attributes: &NodeAttributes) -> Option<Vec<u8>> {
+        let mut out = String::new();
+        match self.part {
+            Part::Header => {
+                self.write_header(&mut out);
+                self.part = if self.format == ExportFormat::Csv {
+                    Part::Edges
+                } else {
+                    Part::Names
+                };
+            }
+            Part::Names => {
+                for nconst in &self.names_in(self.chunk(self.graph.name_count())) {
+                    self.write_node(
+                        &mut out,
+                        &Node {
+                            id: nconst,
+                            kind: "name",
+                            label: attributes.names.get(nconst).map(|name| name.as_str()),
+                            titletype: None,
+                            startyear: None,
+                        },
+                    );
+                }
+                if self.advance(self.graph.name_count()) {
+                    self.part = match self.kind {
+                        GraphKind::Bipartite => Part::Titles,
+                        GraphKind::Projected => Part::Edges,
+                    };
+                }
+            }
+            Part::Titles => {
+                for tconst in &self.titles_in(self.chunk(self.graph.title_count())) {
+                    let basics = attributes.titles.get(tconst);
+                    self.write_node(
+                        &mut out,
+                        &Node {
+                            id: tconst,
+                            kind: "title",
+                            label: basics.and_then(|basics| basics.primarytitle.as_deref()),
+                            titletype: basics.and_then(|basics| basics.titletype.as_deref()),
+                            startyear: basics.and_then(|basics| basics.startyear),
+                        },
+                    );
+                }
+                if self.advance(self.graph.title_count()) {
+                    self.part = Part::Edges;
+                }
+            }
+            Part::Edges => {
+                if self.next == 0 && self.format == ExportFormat::Gexf {
+                    out.push_str("    </nodes>\n    <edges>\n");
+                }
+                for name in self.chunk(self.graph.name_count()) {
+                    self.write_edges_of(&mut out, name);
+                }
+                if self.advance(self.graph.name_count()) {
+                    self.part = Part::Footer;
+                }
+            }
+            Part::Footer => {
+                self.write_footer(&mut out);
+                self.part = Part::Done;
+            }
+            Part::Done => return None,
+        }
+        Some(out.into_bytes())
+    }
+
+    /// The ids of the names in `range` with a role accepted by the filter
+    fn names_in(&self, range: Range<u32>) -> Vec<String> {
+        range
+            .filter(|name| {
+                self.graph
+                    .filtered_titles_of(&self.filter, *name)
+                    .next()
+                    .is_some()
+            })
+            .map(|name| self.graph.nconst(name))
+            .collect()
+    }
+
+    /// The ids of the titles in `range` accepted by the filter
+    fn titles_in(&self, range: Range<u32>) -> Vec<String> {
+        range
+            .filter(|title| self.graph.accepts_title(&self.filter, *title))
+            .map(|title| self.graph.tconst(title))
+            .collect()
+    }
+
+    fn chunk(&self, count: usize) -> Range<u32> {
+        self.next..(self.next + CHUNK_SIZE).min(count as u32)
+    }
+
+    /// Move to the next chunk. Returns true, and starts over at 0, at the end of the part.
+    fn advance(&mut self, count: usize) -> bool {
+        self.next = (self.next + CHUNK_SIZE).min(count as u32);
+        if self.next as usize == count {
+            self.next = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn write_edges_of(&mut self, out: &mut String, name: u32) {
+        let source = self.graph.nconst(name);
+        match self.kind {
+            GraphKind::Bipartite => {
+                let titles: Vec<u32> = self.graph.filtered_titles_of(&self.filter, name).collect();
+                for title in titles {
+                    self.write_edge(out, &source, &self.graph.tconst(title), None);
+                }
+            }
+            GraphKind::Projected => {
+                // every pair of names is written once, from the name with the lower id
+                let mut weights: HashMap<u32, u32> = HashMap::new();
+                for title in self.graph.filtered_titles_of(&self.filter, name) {
+                    for costar in self.graph.filtered_names_of(&self.filter, title) {
+                        if costar > name {
+                            *weights.entry(costar).or_default() += 1;
+                        }
+                    }
+                }
+                let mut costars: Vec<(u32, u32)> = weights.into_iter().collect();
+                costars.sort_unstable();
+                for (costar, weight) in costars {
+                    self.write_edge(out, &source, &self.graph.nconst(costar), Some(weight));
+                }
+            }
+        }
+    }
+
+    fn write_header(&self, out: &mut String) {
+        match self.format {
+            ExportFormat::GraphMl => {
+                out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+                out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+                out.push_str(
+                    "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+                );
+                out.push_str(
+                    "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+                );
+                out.push_str("  <key id=\"titletype\" for=\"node\" attr.name=\"titletype\" attr.type=\"string\"/>\n");
+                out.push_str("  <key id=\"startyear\" for=\"node\" attr.name=\"startyear\" attr.type=\"int\"/>\n");
+                out.push_str(
+                    "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n",
+                );
+                out.push_str("  <graph id=\"imdb\" edgedefault=\"undirected\">\n");
+            }
+            ExportFormat::Gexf => {
+                out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+                out.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+                out.push_str("  <graph mode=\"static\" defaultedgetype=\"undirected\">\n");
+                out.push_str("    <attributes class=\"node\">\n");
+                out.push_str("      <attribute id=\"kind\" title=\"kind\" type=\"string\"/>\n");
+                out.push_str(
+                    "      <attribute id=\"titletype\" title=\"titletype\" type=\"string\"/>\n",
+                );
+                out.push_str(
+                    "      <attribute id=\"startyear\" title=\"startyear\" type=\"integer\"/>\n",
+                );
+                out.push_str("    </attributes>\n");
+                out.push_str("    <nodes>\n");
+            }
+            ExportFormat::Dot => out.push_str("graph imdb {\n"),
+            ExportFormat::Csv => match self.kind {
+                GraphKind::Bipartite => out.push_str("source,target\n"),
+                GraphKind::Projected => out.push_str("source,target,weight\n"),
+            },
+        }
+    }
+
+    fn write_footer(&self, out: &mut String) {
+        match self.format {
+            ExportFormat::GraphMl => out.push_str("  </graph>\n</graphml>\n"),
+            ExportFormat::Gexf => out.push_str("    </edges>\n  </graph>\n</gexf>\n"),
+            ExportFormat::Dot => out.push_str("}\n"),
+            ExportFormat::Csv => {}
+        }
+    }
+
+    fn write_node(&self, out: &mut String, node: &Node) {
+        match self.format {
+            ExportFormat::GraphMl => {
+                out.push_str(&format!(
+                    "    <node id=\"{}\"><data key=\"kind\">{}</data>",
+                    node.id, node.kind
+                ));
+                if let Some(label) = node.label {
+                    out.push_str(&format!("<data key=\"label\">{}</data>", xml_escape(label)));
+                }
+                if let Some(titletype) = node.titletype {
+                    out.push_str(&format!(
+                        "<data key=\"titletype\">{}</data>",
+                        xml_escape(titletype)
+                    ));
+                }
+                if let Some(startyear) = node.startyear {
+                    out.push_str(&format!("<data key=\"startyear\">{}</data>", startyear));
+                }
+                out.push_str("</node>\n");
+            }
+            ExportFormat::Gexf => {
+                out.push_str(&format!(
+                    "      <node id=\"{}\" label=\"{}\"><attvalues><attvalue for=\"kind\" value=\"{}\"/>",
+                    node.id,
+                    xml_escape(node.label.unwrap_or(node.id)),
+                    node.kind
+                ));
+                if let Some(titletype) = node.titletype {
+                    out.push_str(&format!(
+                        "<attvalue for=\"titletype\" value=\"{}\"/>",
+                        xml_escape(titletype)
+                    ));
+                }
+                if let Some(startyear) = node.startyear {
+                    out.push_str(&format!(
+                        "<attvalue for=\"startyear\" value=\"{}\"/>",
+                        startyear
+                    ));
+                }
+                out.push_str("</attvalues></node>\n");
+            }
+            ExportFormat::Dot => {
+                out.push_str(&format!("  \"{}\" [kind=\"{}\"", node.id, node.kind));
+                if let Some(label) = node.label {
+                    out.push_str(&format!(", label=\"{}\"", dot_escape(label)));
+                }
+                if let Some(titletype) = node.titletype {
+                    out.push_str(&format!(", titletype=\"{}\"", dot_escape(titletype)));
+                }
+                if let Some(startyear) = node.startyear {
+                    out.push_str(&format!(", startyear={}", startyear));
+                }
+                out.push_str("];\n");
+            }
+            ExportFormat::Csv => {}
+        }
+    }
+
+    fn write_edge(&mut self, out: &mut String, source: &str, target: &str, weight: Option<u32>) {
+        match (self.format, weight) {
+            (ExportFormat::GraphMl, None) => out.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\"/>\n",
+                source, target
+            )),
+            (ExportFormat::GraphMl, Some(weight)) => out.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data></edge>\n",
+                source, target, weight
+            )),
+            (ExportFormat::Gexf, None) => out.push_str(&format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\"/>\n",
+                self.edge_count, source, target
+            )),
+            (ExportFormat::Gexf, Some(weight)) => out.push_str(&format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"/>\n",
+                self.edge_count, source, target, weight
+            )),
+            (ExportFormat::Dot, None) => {
+                out.push_str(&format!("  \"{}\" -- \"{}\";\n", source, target))
+            }
+            (ExportFormat::Dot, Some(weight)) => out.push_str(&format!(
+                "  \"{}\" -- \"{}\" [weight={}];\n",
+                source, target, weight
+            )),
+            (ExportFormat::Csv, None) => out.push_str(&format!("{},{}\n", source, target)),
+            (ExportFormat::Csv, Some(weight)) => {
+                out.push_str(&format!("{},{},{}\n", source, target, weight))
+            }
+        }
+        self.edge_count += 1;
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // control characters are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{dot_escape, xml_escape};
+
+    #[test]
+    fn escape_labels() {
+        assert_eq!(
+            xml_escape("Tom & Jerry's <\"Movie\">\u{1}"),
+            "Tom &amp; Jerry&apos;s &lt;&quot;Movie&quot;&gt;"
+        );
+        assert_eq!(dot_escape("say \"hi\" \\o/"), "say \\\"hi\\\" \\\\o/");
+    }
+}
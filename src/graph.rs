@@ -0,0 +1,712 @@
+use std::io::{self, Read, Write};
+
+use crate::error::ApiError;
+use crate::schemas::TitleFilterParams;
+
+pub const TITLE_PREFIX: &str = "tt";
+pub const NAME_PREFIX: &str = "nm";
+
+/// The numeric part of an IMDb id like `tt0112384` or `nm0000102`
+pub fn parse_id(prefix: &str, id: &str) -> Option<u32> {
+    let digits = id.strip_prefix(prefix)?;
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Format an IMDb id from its numeric part, IMDb pads the numbers to at least 7 digits
+pub fn format_id(prefix: &str, number: u32) -> String {
+    format!("{}{:07}", prefix, number)
+}
+
+/// The properties of a title that searches can filter on, in compact form.
+/// Zero stands for an unknown value, title types and genres are indexes in the vocabularies of the graph.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TitleAttributes {
+    title_type: u8,
+    start_year: u16,
+    num_votes: u32,
+    genres: u32,
+    rating: u8,
+}
+
+/// The principal categories loaded into the graph when none are configured,
+/// and the categories connecting names when a search does not choose them
+pub const DEFAULT_CATEGORIES: &[&str] = &["actor", "actress"];
+
+/// Roles are bitmasks of categories, so a graph holds at most this many categories
+pub const MAX_CATEGORIES: usize = 16;
+
+/// Collects title to name mappings and title attributes, to be turned into an `ActorGraph`
+#[derive(Debug, Default)]
+pub struct GraphBuilder {
+    edges: Vec<(u32, u32, u16)>,
+    attributes: Vec<(u32, TitleAttributes)>,
+    title_types: Vec<String>,
+    genres: Vec<String>,
+    categories: Vec<String>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> GraphBuilder {
+        GraphBuilder {
+            edges: Vec::with_capacity(1_000_000),
+            ..Default::default()
+        }
+    }
+
+    /// Returns false if the id is not a valid IMDb id
+    pub fn add_title(
+        &mut self,
+        tconst: &str,
+        title_type: Option<&str>,
+        start_year: Option<i32>,
+        genres: Option<&str>,
+        num_votes: Option<i32>,
+        average_rating: Option<f64>,
+    ) -> bool {
+        if let Some(title) = parse_id(TITLE_PREFIX, tconst) {
+            let attributes = TitleAttributes {
+                title_type: title_type.map_or(0, |t| intern(&mut self.title_types, t, 255) as u8),
+                start_year: start_year.map_or(0, |y| y.clamp(0, u16::MAX as i32) as u16),
+                num_votes: num_votes.map_or(0, |v| v.max(0) as u32),
+                genres: genres.map_or(0, |g| {
+                    g.split(',')
+                        .filter(|genre| !genre.is_empty())
+                        .map(|genre| intern(&mut self.genres, genre, 32))
+                        .filter(|id| *id > 0)
+                        .fold(0, |mask, id| mask | 1 << (id - 1))
+                }),
+                rating: average_rating.map_or(0, |r| (r * 10.0).round().clamp(0.0, 255.0) as u8),
+            };
+            self.attributes.push((title, attributes));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Make a category known to searches, even if no principal has it.
+    /// Returns false if there are too many categories.
+    pub fn add_category(&mut self, category: &str) -> bool {
+        intern(&mut self.categories, category, MAX_CATEGORIES) > 0
+    }
+
+    /// Add the principal `nconst` of `tconst` in the given category.
+    /// Returns false if one of the ids is not a valid IMDb id, or there are too many categories.
+    pub fn add(&mut self, tconst: &str, nconst: &str, category: &str) -> bool {
+        match (
+            parse_id(TITLE_PREFIX, tconst),
+            parse_id(NAME_PREFIX, nconst),
+            intern(&mut self.categories, category, MAX_CATEGORIES),
+        ) {
+            (Some(title), Some(name), category) if category > 0 => {
+                self.edges.push((title, name, 1 << (category - 1)));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn build(mut self) -> ActorGraph {
+        // a name may have several roles in a title, the edge keeps all of them
+        self.edges.sort_unstable();
+        self.edges.dedup_by(|edge, kept| {
+            let same = edge.0 == kept.0 && edge.1 == kept.1;
+            if same {
+                kept.2 |= edge.2;
+            }
+            same
+        });
+
+        let mut titles: Vec<u32> = self.edges.iter().map(|e| e.0).collect();
+        titles.dedup();
+        let mut names: Vec<u32> = self.edges.iter().map(|e| e.1).collect();
+        names.sort_unstable();
+        names.dedup();
+
+        // the edges are sorted by title, so the title side is filled in order
+        let mut title_offsets: Vec<u32> = vec![0; titles.len() + 1];
+        let mut title_names: Vec<u32> = Vec::with_capacity(self.edges.len());
+        let mut title_roles: Vec<u16> = Vec::with_capacity(self.edges.len());
+        let mut name_counts: Vec<u32> = vec![0; names.len()];
+        let mut title = 0;
+        for (title_number, name_number, roles) in &self.edges {
+            while titles[title] != *title_number {
+                title += 1;
+                title_offsets[title] = title_names.len() as u32;
+            }
+            let name = names.binary_search(name_number).unwrap();
+            title_names.push(name as u32);
+            title_roles.push(*roles);
+            name_counts[name] += 1;
+        }
+        title_offsets[titles.len()] = title_names.len() as u32;
+        drop(self.edges);
+
+        let mut attributes: Vec<TitleAttributes> = vec![TitleAttributes::default(); titles.len()];
+        for (title_number, title_attributes) in &self.attributes {
+            if let Ok(title) = titles.binary_search(title_number) {
+                attributes[title] = *title_attributes;
+            }
+        }
+
+        let mut name_offsets: Vec<u32> = Vec::with_capacity(names.len() + 1);
+        let mut total = 0;
+        for count in &name_counts {
+            name_offsets.push(total);
+            total += count;
+        }
+        name_offsets.push(total);
+
+        // iterating the titles in order keeps the titles of each name sorted
+        let mut name_titles: Vec<u32> = vec![0; total as usize];
+        let mut name_roles: Vec<u16> = vec![0; total as usize];
+        let mut fill: Vec<u32> = name_offsets[..names.len()].to_vec();
+        for title in 0..titles.len() {
+            let start = title_offsets[title] as usize;
+            let end = title_offsets[title + 1] as usize;
+            for edge in start..end {
+                let pos = &mut fill[title_names[edge] as usize];
+                name_titles[*pos as usize] = title as u32;
+                name_roles[*pos as usize] = title_roles[edge];
+                *pos += 1;
+            }
+        }
+
+        ActorGraph {
+            titles,
+            names,
+            title_offsets,
+            title_names,
+            name_offsets,
+            name_titles,
+            title_roles,
+            name_roles,
+            attributes,
+            title_types: self.title_types,
+            genres: self.genres,
+            categories: self.categories,
+        }
+    }
+}
+
+/// The 1-based index of `value` in the vocabulary, added if missing. Returns 0 if the vocabulary is full.
+fn intern(vocabulary: &mut Vec<String>, value: &str, max_len: usize) -> usize {
+    if let Some(idx) = vocabulary.iter().position(|v| v == value) {
+        idx + 1
+    } else if vocabulary.len() < max_len {
+        vocabulary.push(value.to_string());
+        vocabulary.len()
+    } else {
+        0
+    }
+}
+
+/// Restricts the titles connecting names in a search, and the roles names must have in them
+#[derive(Debug, Default)]
+pub struct TitleFilter {
+    categories: Option<u16>,
+    title_types: Option<Vec<u8>>,
+    genres: Option<u32>,
+    start_years: Option<(u16, u16)>,
+    min_votes: u32,
+    min_rating: u8,
+}
+
+impl TitleFilter {
+    fn accepts(&self, attributes: &TitleAttributes) -> bool {
+        self.title_types
+            .as_ref()
+            .is_none_or(|types| types.contains(&attributes.title_type))
+            && self
+                .genres
+                .is_none_or(|genres| genres & attributes.genres != 0)
+            && self.start_years.is_none_or(|(from, to)| {
+                attributes.start_year != 0
+                    && attributes.start_year >= from
+                    && attributes.start_year <= to
+            })
+            && attributes.num_votes >= self.min_votes
+            && attributes.rating >= self.min_rating
+    }
+
+    fn accepts_roles(&self, roles: u16) -> bool {
+        self.categories
+            .is_none_or(|categories| categories & roles != 0)
+    }
+}
+
+/// The bipartite title - name graph with interned ids, in compressed sparse row layout.
+/// Titles and names are identified by their index in the ascending list of their IMDb numbers,
+/// so iterating the adjacency lists returns them in IMDb id order.
+/// Each edge has the bitmask of the categories of the name in the title, next to the adjacency lists.
+#[derive(Debug, Default)]
+pub struct ActorGraph {
+    titles: Vec<u32>,
+    names: Vec<u32>,
+    title_offsets: Vec<u32>,
+    title_names: Vec<u32>,
+    name_offsets: Vec<u32>,
+    name_titles: Vec<u32>,
+    title_roles: Vec<u16>,
+    name_roles: Vec<u16>,
+    attributes: Vec<TitleAttributes>,
+    title_types: Vec<String>,
+    genres: Vec<String>,
+    categories: Vec<String>,
+}
+
+impl ActorGraph {
+    /// Build a filter from its query parameters. Categories, title types and genres are comma-separated lists,
+    /// unknown values are reported as errors. Without categories, the `DEFAULT_CATEGORIES` of the graph are used.
+    pub fn title_filter(&self, params: &TitleFilterParams) -> Result<TitleFilter, ApiError> {
+        fn ids(vocabulary: &[String], list: &str, kind: &str) -> Result<Vec<usize>, ApiError> {
+            list.split(',')
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    vocabulary
+                        .iter()
+                        .position(|v| v == value)
+                        .map(|idx| idx + 1)
+                        .ok_or_else(|| {
+                            ApiError::bad_input(Some(value), format!("Unknown {} {}", kind, value))
+                        })
+                })
+                .collect()
+        }
+
+        let mask = |ids: Vec<usize>| ids.iter().fold(0, |mask, id| mask | 1 << (id - 1));
+        let categories = match &params.categories {
+            Some(list) => Some(mask(ids(&self.categories, list, "category")?) as u16),
+            None => {
+                let defaults = DEFAULT_CATEGORIES
+                    .iter()
+                    .filter_map(|category| ids(&self.categories, category, "category").ok())
+                    .flatten()
+                    .collect();
+                Some(mask(defaults) as u16).filter(|mask| *mask != 0)
+            }
+        };
+        let title_types = match &params.titletype {
+            Some(list) => Some(
+                ids(&self.title_types, list, "title type")?
+                    .iter()
+                    .map(|id| *id as u8)
+                    .collect(),
+            ),
+            None => None,
+        };
+        let genres = match &params.genres {
+            Some(list) => Some(mask(ids(&self.genres, list, "genre")?)),
+            None => None,
+        };
+        let start_years = if params.startyear_from.is_some() || params.startyear_to.is_some() {
+            let year = |y: i32| y.clamp(1, u16::MAX as i32) as u16;
+            Some((
+                params.startyear_from.map_or(1, year),
+                params.startyear_to.map_or(u16::MAX, year),
+            ))
+        } else {
+            None
+        };
+
+        Ok(TitleFilter {
+            categories,
+            title_types,
+            genres,
+            start_years,
+            min_votes: params.min_numvotes.map_or(0, |v| v.max(0) as u32),
+            min_rating: params
+                .min_averagerating
+                .map_or(0, |r| (r * 10.0).round().clamp(0.0, 255.0) as u8),
+        })
+    }
+
+    /// The titles of `name` accepted by the filter, in which the name has one of the accepted roles
+    pub fn filtered_titles_of<'a>(
+        &'a self,
+        filter: &'a TitleFilter,
+        name: u32,
+    ) -> impl Iterator<Item = u32> + 'a {
+        let start = self.name_offsets[name as usize] as usize;
+        self.titles_of(name)
+            .iter()
+            .zip(&self.name_roles[start..])
+            .filter(move |(title, roles)| {
+                filter.accepts_roles(**roles) && filter.accepts(&self.attributes[**title as usize])
+            })
+            .map(|(title, _)| *title)
+    }
+
+    /// The names of `title` that have one of the roles accepted by the filter
+    pub fn filtered_names_of<'a>(
+        &'a self,
+        filter: &'a TitleFilter,
+        title: u32,
+    ) -> impl Iterator<Item = u32> + 'a {
+        let start = self.title_offsets[title as usize] as usize;
+        self.names_of(title)
+            .iter()
+            .zip(&self.title_roles[start..])
+            .filter(|(_, roles)| filter.accepts_roles(**roles))
+            .map(|(name, _)| *name)
+    }
+
+    /// Whether the filter accepts `title` and some of its names have one of the accepted roles
+    pub fn accepts_title(&self, filter: &TitleFilter, title: u32) -> bool {
+        filter.accepts(&self.attributes[title as usize])
+            && self.filtered_names_of(filter, title).next().is_some()
+    }
+
+    pub fn name_id(&self, nconst: &str) -> Option<u32> {
+        let number = parse_id(NAME_PREFIX, nconst)?;
+        self.names.binary_search(&number).ok().map(|id| id as u32)
+    }
+
+    pub fn tconst(&self, title: u32) -> String {
+        format_id(TITLE_PREFIX, self.titles[title as usize])
+    }
+
+    pub fn nconst(&self, name: u32) -> String {
+        format_id(NAME_PREFIX, self.names[name as usize])
+    }
+
+    pub fn names_of(&self, title: u32) -> &[u32] {
+        let start = self.title_offsets[title as usize] as usize;
+        let end = self.title_offsets[title as usize + 1] as usize;
+        &self.title_names[start..end]
+    }
+
+    pub fn titles_of(&self, name: u32) -> &[u32] {
+        let start = self.name_offsets[name as usize] as usize;
+        let end = self.name_offsets[name as usize + 1] as usize;
+        &self.name_titles[start..end]
+    }
+
+    pub fn title_count(&self) -> usize {
+        self.titles.len()
+    }
+
+    pub fn name_count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        for array in [
+            &self.titles,
+            &self.names,
+            &self.title_offsets,
+            &self.title_names,
+            &self.name_offsets,
+            &self.name_titles,
+        ] {
+            write_array(w, array)?;
+        }
+        let attribute = |f: fn(&TitleAttributes) -> u32| -> Vec<u32> {
+            self.attributes.iter().map(f).collect()
+        };
+        write_array(w, &attribute(|a| a.title_type as u32))?;
+        write_array(w, &attribute(|a| a.start_year as u32))?;
+        write_array(w, &attribute(|a| a.num_votes))?;
+        write_array(w, &attribute(|a| a.genres))?;
+        write_array(w, &attribute(|a| a.rating as u32))?;
+        write_roles(w, &self.title_roles)?;
+        write_roles(w, &self.name_roles)?;
+        write_strings(w, &self.title_types)?;
+        write_strings(w, &self.genres)?;
+        write_strings(w, &self.categories)
+    }
+
+    pub fn read_from(r: &mut impl Read) -> io::Result<ActorGraph> {
+        let titles = read_array(r)?;
+        let names = read_array(r)?;
+        let title_offsets = read_array(r)?;
+        let title_names = read_array(r)?;
+        let name_offsets = read_array(r)?;
+        let name_titles = read_array(r)?;
+        let title_type = read_array(r)?;
+        let start_year = read_array(r)?;
+        let num_votes = read_array(r)?;
+        let genres = read_array(r)?;
+        let rating = read_array(r)?;
+        let attribute_arrays = [&title_type, &start_year, &num_votes, &genres, &rating];
+        if attribute_arrays.iter().any(|a| a.len() != titles.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "inconsistent title attributes",
+            ));
+        }
+        let attributes = (0..titles.len())
+            .map(|i| TitleAttributes {
+                title_type: title_type[i] as u8,
+                start_year: start_year[i] as u16,
+                num_votes: num_votes[i],
+                genres: genres[i],
+                rating: rating[i] as u8,
+            })
+            .collect();
+
+        let graph = ActorGraph {
+            titles,
+            names,
+            title_offsets,
+            title_names,
+            name_offsets,
+            name_titles,
+            title_roles: read_roles(r)?,
+            name_roles: read_roles(r)?,
+            attributes,
+            title_types: read_strings(r)?,
+            genres: read_strings(r)?,
+            categories: read_strings(r)?,
+        };
+        if graph.is_consistent() {
+            Ok(graph)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "inconsistent graph arrays",
+            ))
+        }
+    }
+
+    /// Check that ids can be looked up and the adjacency lists indexed without panics
+    fn is_consistent(&self) -> bool {
+        fn valid_csr(offsets: &[u32], adjacency: &[u32], nodes: usize, targets: usize) -> bool {
+            offsets.len() == nodes + 1
+                && offsets[0] == 0
+                && offsets[nodes] as usize == adjacency.len()
+                && offsets.windows(2).all(|w| w[0] <= w[1])
+                && adjacency.iter().all(|x| (*x as usize) < targets)
+        }
+        fn ascending(ids: &[u32]) -> bool {
+            ids.windows(2).all(|w| w[0] < w[1])
+        }
+        ascending(&self.titles)
+            && ascending(&self.names)
+            && valid_csr(
+                &self.title_offsets,
+                &self.title_names,
+                self.titles.len(),
+                self.names.len(),
+            )
+            && valid_csr(
+                &self.name_offsets,
+                &self.name_titles,
+                self.names.len(),
+                self.titles.len(),
+            )
+            && self.title_roles.len() == self.title_names.len()
+            && self.name_roles.len() == self.name_titles.len()
+    }
+}
+
+fn write_array(w: &mut impl Write, array: &[u32]) -> io::Result<()> {
+    w.write_all(&(array.len() as u64).to_le_bytes())?;
+    for chunk in array.chunks(1 << 14) {
+        let bytes: Vec<u8> = chunk.iter().flat_map(|x| x.to_le_bytes()).collect();
+        w.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+/// Vocabulary entries are short, longer strings are taken as a sign of a corrupt file
+const MAX_STRING_LEN: usize = 1024;
+
+fn write_strings(w: &mut impl Write, strings: &[String]) -> io::Result<()> {
+    w.write_all(&(strings.len() as u32).to_le_bytes())?;
+    for s in strings {
+        w.write_all(&(s.len() as u32).to_le_bytes())?;
+        w.write_all(s.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_strings(r: &mut impl Read) -> io::Result<Vec<String>> {
+    let read_len = |r: &mut dyn Read| -> io::Result<usize> {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)?;
+        Ok(u32::from_le_bytes(len) as usize)
+    };
+    let count = read_len(r)?;
+    let mut strings: Vec<String> = Vec::new();
+    for _ in 0..count {
+        let len = read_len(r)?;
+        if len > MAX_STRING_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "string too long",
+            ));
+        }
+        let mut bytes = vec![0u8; len];
+        r.read_exact(&mut bytes)?;
+        strings.push(
+            String::from_utf8(bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        );
+    }
+    Ok(strings)
+}
+
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    w.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    w.write_all(bytes)
+}
+
+fn read_bytes(r: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len) as usize;
+    let mut bytes: Vec<u8> = Vec::with_capacity(len.min(1 << 30));
+    r.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() == len {
+        Ok(bytes)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated byte array",
+        ))
+    }
+}
+
+fn write_roles(w: &mut impl Write, roles: &[u16]) -> io::Result<()> {
+    let bytes: Vec<u8> = roles.iter().flat_map(|x| x.to_le_bytes()).collect();
+    write_bytes(w, &bytes)
+}
+
+fn read_roles(r: &mut impl Read) -> io::Result<Vec<u16>> {
+    let bytes = read_bytes(r)?;
+    if bytes.len() % 2 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "truncated role array",
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect())
+}
+
+fn read_array(r: &mut impl Read) -> io::Result<Vec<u32>> {
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len) as usize;
+    let mut array: Vec<u32> = Vec::with_capacity(len.min(1 << 28));
+    let mut bytes = vec![0u8; 4 << 14];
+    while array.len() < len {
+        let count = (len - array.len()).min(1 << 14);
+        r.read_exact(&mut bytes[..count * 4])?;
+        array.extend(
+            bytes[..count * 4]
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+    }
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{
+        format_id, parse_id, ActorGraph, GraphBuilder, MAX_CATEGORIES, NAME_PREFIX, TITLE_PREFIX,
+    };
+    use crate::schemas::TitleFilterParams;
+
+    #[test]
+    fn imdb_ids() {
+        assert_eq!(parse_id(TITLE_PREFIX, "tt0112384"), Some(112384));
+        assert_eq!(parse_id(TITLE_PREFIX, "tt10872600"), Some(10872600));
+        assert_eq!(parse_id(TITLE_PREFIX, "nm0000102"), None);
+        assert_eq!(parse_id(NAME_PREFIX, "nm+102"), None);
+        assert_eq!(parse_id(NAME_PREFIX, "nm"), None);
+        assert_eq!(format_id(TITLE_PREFIX, 112384), "tt0112384");
+        assert_eq!(format_id(TITLE_PREFIX, 10872600), "tt10872600");
+    }
+
+    #[test]
+    fn build_adjacency() {
+        let mut builder = GraphBuilder::new();
+        for (tconst, nconst) in [
+            ("tt0000030", "nm0000002"),
+            ("tt0000010", "nm0000003"),
+            ("tt0000010", "nm0000001"),
+            ("tt0000030", "nm0000001"),
+            ("tt0000010", "nm0000001"),
+        ] {
+            assert!(builder.add(tconst, nconst, "actor"));
+        }
+        assert!(!builder.add("x", "nm0000001", "actor"));
+        let graph = builder.build();
+
+        assert_eq!(graph.title_count(), 2);
+        assert_eq!(graph.name_count(), 3);
+        let n1 = graph.name_id("nm0000001").unwrap();
+        assert_eq!(graph.titles_of(n1), &[0, 1]);
+        let (t10, t30) = (0, 1);
+        assert_eq!(graph.tconst(t10), "tt0000010");
+        assert_eq!(graph.names_of(t10).len(), 2);
+        assert_eq!(
+            graph
+                .names_of(t30)
+                .iter()
+                .map(|n| graph.nconst(*n))
+                .collect::<Vec<String>>(),
+            vec!["nm0000001", "nm0000002"]
+        );
+        assert_eq!(graph.tconst(t30), "tt0000030");
+        assert!(graph.name_id("nm0000004").is_none());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut builder = GraphBuilder::new();
+        builder.add("tt0000010", "nm0000003", "actor");
+        builder.add("tt0000010", "nm0000001", "actor");
+        builder.add("tt0000030", "nm0000001", "actor");
+        let graph = builder.build();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        graph.write_to(&mut bytes).unwrap();
+        let read = ActorGraph::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.title_count(), 2);
+        assert_eq!(read.name_count(), 2);
+        let n1 = read.name_id("nm0000001").unwrap();
+        assert_eq!(read.titles_of(n1), graph.titles_of(n1));
+        assert_eq!(read.names_of(0), graph.names_of(0));
+
+        assert!(ActorGraph::read_from(&mut &bytes[..bytes.len() - 1]).is_err());
+        bytes[8] = 99;
+        assert!(ActorGraph::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn many_categories() {
+        let mut builder = GraphBuilder::new();
+        for n in 1..=MAX_CATEGORIES {
+            assert!(builder.add_category(&format!("category{}", n)));
+        }
+        assert!(!builder.add_category("one too many"));
+        builder.add("tt0000010", "nm0000001", "category1");
+        builder.add(
+            "tt0000010",
+            "nm0000002",
+            &format!("category{}", MAX_CATEGORIES),
+        );
+        let mut bytes: Vec<u8> = Vec::new();
+        builder.build().write_to(&mut bytes).unwrap();
+        let graph = ActorGraph::read_from(&mut bytes.as_slice()).unwrap();
+
+        let params = TitleFilterParams {
+            categories: Some(format!("category{}", MAX_CATEGORIES)),
+            ..Default::default()
+        };
+        let filter = graph.title_filter(&params).unwrap();
+        let names: Vec<u32> = graph.filtered_names_of(&filter, 0).collect();
+        assert_eq!(names, vec![graph.name_id("nm0000002").unwrap()]);
+    }
+}
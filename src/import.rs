@@ -0,0 +1,541 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use flate2::read::MultiGzDecoder;
+use rocket_db_pools::sqlx;
+
+use crate::migrate;
+
+/// The null marker used by the IMDb dumps
+const TSV_NULL: &str = "\\N";
+
+/// Size of the buffer collected before it is sent to PostgreSQL in a `COPY` data message
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnKind {
+    Text,
+    Number,
+    Bool,
+    Array,
+}
+
+/// One of the IMDb dataset files and the table it is loaded into
+pub struct Dataset {
+    pub file: &'static str,
+    pub table: &'static str,
+    pub columns: &'static [(&'static str, ColumnKind)],
+}
+
+/// The datasets in load order, with their TSV headers.
+/// Table columns are the lower-case variants of the header names.
+pub const DATASETS: &[Dataset] = &[
+    Dataset {
+        file: "name.basics.tsv",
+        table: "name_basics",
+        columns: &[
+            ("nconst", ColumnKind::Text),
+            ("primaryName", ColumnKind::Text),
+            ("birthYear", ColumnKind::Number),
+            ("deathYear", ColumnKind::Number),
+            ("primaryProfession", ColumnKind::Array),
+            ("knownForTitles", ColumnKind::Array),
+        ],
+    },
+    Dataset {
+        file: "title.basics.tsv",
+        table: "title_basics",
+        columns: &[
+            ("tconst", ColumnKind::Text),
+            ("titleType", ColumnKind::Text),
+            ("primaryTitle", ColumnKind::Text),
+            ("originalTitle", ColumnKind::Text),
+            ("isAdult", ColumnKind::Bool),
+            ("startYear", ColumnKind::Number),
+            ("endYear", ColumnKind::Number),
+            ("runtimeMinutes", ColumnKind::Number),
+            ("genres", ColumnKind::Array),
+        ],
+    },
+    Dataset {
+        file: "title.ratings.tsv",
+        table: "title_ratings",
+        columns: &[
+            ("tconst", ColumnKind::Text),
+            ("averageRating", ColumnKind::Number),
+            ("numVotes", ColumnKind::Number),
+        ],
+    },
+    Dataset {
+        file: "title.principals.tsv",
+        table: "title_principals",
+        columns: &[
+            ("tconst", ColumnKind::Text),
+            ("ordering", ColumnKind::Number),
+            ("nconst", ColumnKind::Text),
+            ("category", ColumnKind::Text),
+            ("job", ColumnKind::Text),
+            ("characters", ColumnKind::Text),
+        ],
+    },
+    Dataset {
+        file: "title.akas.tsv",
+        table: "title_akas",
+        columns: &[
+            ("titleId", ColumnKind::Text),
+            ("ordering", ColumnKind::Number),
+            ("title", ColumnKind::Text),
+            ("region", ColumnKind::Text),
+            ("language", ColumnKind::Text),
+            ("types", ColumnKind::Text),
+            ("attributes", ColumnKind::Text),
+            ("isOriginalTitle", ColumnKind::Bool),
+        ],
+    },
+    Dataset {
+        file: "title.episode.tsv",
+        table: "title_episode",
+        columns: &[
+            ("tconst", ColumnKind::Text),
+            ("parentTconst", ColumnKind::Text),
+            ("seasonNumber", ColumnKind::Number),
+            ("episodeNumber", ColumnKind::Number),
+        ],
+    },
+    Dataset {
+        file: "title.crew.tsv",
+        table: "title_crew",
+        columns: &[
+            ("tconst", ColumnKind::Text),
+            ("directors", ColumnKind::Array),
+            ("writers", ColumnKind::Array),
+        ],
+    },
+];
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(PathBuf, io::Error),
+    Db(String, sqlx::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(path, err) => write!(f, "Error reading {}: {}", path.display(), err),
+            ImportError::Db(table, err) => write!(f, "Error loading table {}: {}", table, err),
+            ImportError::Format(path, msg) => {
+                write!(f, "Invalid dataset {}: {}", path.display(), msg)
+            }
+        }
+    }
+}
+
+/// A line of an IMDb TSV file, with `\N` translated to `None`
+#[derive(Debug)]
+pub struct TsvRecord {
+    values: Vec<Option<String>>,
+}
+
+impl TsvRecord {
+    fn parse(line: &str) -> TsvRecord {
+        TsvRecord {
+            values: line
+                .split('\t')
+                .map(|value| {
+                    if value == TSV_NULL {
+                        None
+                    } else {
+                        Some(value.to_string())
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        self.values.get(idx).and_then(|v| v.as_deref())
+    }
+
+    /// Array-valued columns (`genres`, `knownForTitles`, ...) are comma-separated lists
+    pub fn array(&self, idx: usize) -> Vec<&str> {
+        self.get(idx)
+            .map(|v| v.split(',').filter(|x| !x.is_empty()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Reads a plain or gzip-compressed IMDb TSV file, the first line being the header
+pub struct TsvReader {
+    header: Vec<String>,
+    lines: Lines<BufReader<Box<dyn Read + Send>>>,
+}
+
+impl TsvReader {
+    pub fn open(path: &Path) -> io::Result<TsvReader> {
+        let file = File::open(path)?;
+        let read: Box<dyn Read + Send> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(MultiGzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        TsvReader::new(read)
+    }
+
+    pub fn new(read: Box<dyn Read + Send>) -> io::Result<TsvReader> {
+        let mut lines = BufReader::with_capacity(1 << 16, read).lines();
+        let header = match lines.next() {
+            Some(line) => line?.split('\t').map(|x| x.to_string()).collect(),
+            None => vec![],
+        };
+        Ok(TsvReader { header, lines })
+    }
+
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.header.iter().position(|h| h == column)
+    }
+}
+
+impl Iterator for TsvReader {
+    type Item = io::Result<TsvRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines
+            .next()
+            .map(|line| line.map(|l| TsvRecord::parse(l.trim_end_matches('\r'))))
+    }
+}
+
+/// Locate the file of a dataset in `dir`, preferring the compressed variant
+pub fn dataset_path(dir: &Path, dataset: &Dataset) -> Option<PathBuf> {
+    [format!("{}.gz", dataset.file), dataset.file.to_string()]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Escape a value for the PostgreSQL `COPY` text format
+fn push_copy_value(buf: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => buf.push_str("\\\\"),
+            '\t' => buf.push_str("\\t"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            _ => buf.push(c),
+        }
+    }
+}
+
+fn push_copy_field(buf: &mut String, record: &TsvRecord, idx: usize, kind: ColumnKind) {
+    match (kind, record.get(idx)) {
+        // boolean columns are not nullable, a missing flag is false like in the SQLite import
+        (ColumnKind::Bool, value) => buf.push_str(if value == Some("1") { "t" } else { "f" }),
+        (_, None) => buf.push_str(TSV_NULL),
+        (ColumnKind::Array, Some(_)) => push_copy_value(buf, &record.array(idx).join(",")),
+        (_, Some(value)) => push_copy_value(buf, value),
+    }
+}
+
+/// Translate a TSV record to a line in `COPY` text format, in the column order of the dataset
+fn push_copy_line(buf: &mut String, record: &TsvRecord, indexes: &[(usize, ColumnKind)]) {
+    let mut delimiter = "";
+    for (idx, kind) in indexes {
+        buf.push_str(delimiter);
+        push_copy_field(buf, record, *idx, *kind);
+        delimiter = "\t";
+    }
+    buf.push('\n');
+}
+
+/// Open the file of a dataset, with the indexes of the dataset columns in the file
+fn open_dataset(
+    path: &Path,
+    dataset: &Dataset,
+) -> Result<(TsvReader, Vec<(usize, ColumnKind)>), ImportError> {
+    let reader = TsvReader::open(path).map_err(|err| ImportError::Io(path.into(), err))?;
+    let mut indexes: Vec<(usize, ColumnKind)> = Vec::new();
+    for (column, kind) in dataset.columns {
+        match reader.column_index(column) {
+            Some(idx) => indexes.push((idx, *kind)),
+            None => {
+                return Err(ImportError::Format(
+                    path.into(),
+                    format!("missing column {}", column),
+                ))
+            }
+        }
+    }
+    Ok((reader, indexes))
+}
+
+/// The table columns of a dataset, separated by commas
+fn table_columns(dataset: &Dataset) -> String {
+    dataset
+        .columns
+        .iter()
+        .map(|(column, _)| column.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Writes the datasets to the tables of one database backend
+#[rocket::async_trait]
+trait DatasetWriter {
+    /// Replace the content of the table of `dataset` by the records of `reader`, returning the number of rows
+    async fn load(
+        &self,
+        path: &Path,
+        dataset: &Dataset,
+        reader: TsvReader,
+        indexes: Vec<(usize, ColumnKind)>,
+    ) -> Result<u64, ImportError>;
+
+    /// Record the import of the tables, once they are all loaded
+    async fn finish(&self, imported: &[&str]) -> Result<(), ImportError>;
+}
+
+#[rocket::async_trait]
+impl DatasetWriter for sqlx::PgPool {
+    async fn load(
+        &self,
+        path: &Path,
+        dataset: &Dataset,
+        mut reader: TsvReader,
+        indexes: Vec<(usize, ColumnKind)>,
+    ) -> Result<u64, ImportError> {
+        let db_err = |err| ImportError::Db(dataset.table.to_string(), err);
+        let mut tx = self.begin().await.map_err(db_err)?;
+        sqlx::query(&format!("TRUNCATE {}", dataset.table))
+            .execute(&mut tx)
+            .await
+            .map_err(db_err)?;
+
+        let mut copy = tx
+            .copy_in_raw(&format!(
+                "COPY {} ({}) FROM STDIN",
+                dataset.table,
+                table_columns(dataset)
+            ))
+            .await
+            .map_err(db_err)?;
+        let mut buf = String::with_capacity(COPY_CHUNK_SIZE + 4096);
+        for record in &mut reader {
+            match record {
+                Ok(record) => push_copy_line(&mut buf, &record, &indexes),
+                Err(err) => {
+                    copy.abort(err.to_string()).await.map_err(db_err)?;
+                    return Err(ImportError::Io(path.into(), err));
+                }
+            }
+            if buf.len() >= COPY_CHUNK_SIZE {
+                copy.send(buf.as_bytes()).await.map_err(db_err)?;
+                buf.clear();
+            }
+        }
+        if !buf.is_empty() {
+            copy.send(buf.as_bytes()).await.map_err(db_err)?;
+        }
+        let rows = copy.finish().await.map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+        Ok(rows)
+    }
+
+    async fn finish(&self, imported: &[&str]) -> Result<(), ImportError> {
+        sqlx::query("INSERT INTO dataset_imports (datasets) VALUES ($1)")
+            .bind(imported.join(","))
+            .execute(self)
+            .await
+            .map_err(|err| ImportError::Db("dataset_imports".to_string(), err))?;
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl DatasetWriter for sqlx::SqlitePool {
+    async fn load(
+        &self,
+        path: &Path,
+        dataset: &Dataset,
+        mut reader: TsvReader,
+        indexes: Vec<(usize, ColumnKind)>,
+    ) -> Result<u64, ImportError> {
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            dataset.table,
+            table_columns(dataset),
+            vec!["?"; indexes.len()].join(", ")
+        );
+
+        let db_err = |err| ImportError::Db(dataset.table.to_string(), err);
+        let mut tx = self.begin().await.map_err(db_err)?;
+        sqlx::query(&format!("DELETE FROM {}", dataset.table))
+            .execute(&mut tx)
+            .await
+            .map_err(db_err)?;
+
+        // SQLite has no bulk load, but prepared inserts in a single transaction come close
+        let mut rows = 0;
+        for record in &mut reader {
+            let record = record.map_err(|err| ImportError::Io(path.into(), err))?;
+            let mut query = sqlx::query(&sql);
+            for (idx, kind) in &indexes {
+                query = match (kind, record.get(*idx)) {
+                    (ColumnKind::Bool, value) => query.bind(value == Some("1")),
+                    (ColumnKind::Array, Some(_)) => query.bind(record.array(*idx).join(",")),
+                    (_, value) => query.bind(value.map(|v| v.to_string())),
+                };
+            }
+            rows += query
+                .execute(&mut tx)
+                .await
+                .map_err(db_err)?
+                .rows_affected();
+        }
+        tx.commit().await.map_err(db_err)?;
+        Ok(rows)
+    }
+
+    async fn finish(&self, imported: &[&str]) -> Result<(), ImportError> {
+        if imported.contains(&"name_basics") {
+            let start_time = SystemTime::now();
+            let rows = migrate::fill_searchnames(self)
+                .await
+                .map_err(|err| ImportError::Db("name_basics".to_string(), err))?;
+            println!(
+                "Computed {} search names in {:?} time",
+                rows,
+                start_time.elapsed().unwrap()
+            );
+        }
+
+        sqlx::query("INSERT INTO dataset_imports (datasets) VALUES (?)")
+            .bind(imported.join(","))
+            .execute(self)
+            .await
+            .map_err(|err| ImportError::Db("dataset_imports".to_string(), err))?;
+        Ok(())
+    }
+}
+
+/// Load the datasets found in `dir` with the writer of the database
+async fn import_datasets(writer: &impl DatasetWriter, dir: &Path) -> Result<(), ImportError> {
+    let mut imported: Vec<&str> = Vec::new();
+    for dataset in DATASETS {
+        if let Some(path) = dataset_path(dir, dataset) {
+            let start_time = SystemTime::now();
+            let (reader, indexes) = open_dataset(&path, dataset)?;
+            let rows = writer.load(&path, dataset, reader, indexes).await?;
+            imported.push(dataset.table);
+            println!(
+                "Loaded {} rows from {} to {} in {:?} time",
+                rows,
+                path.display(),
+                dataset.table,
+                start_time.elapsed().unwrap()
+            );
+        } else {
+            println!(
+                "Dataset {} not found in {}, skipping",
+                dataset.file,
+                dir.display()
+            );
+        }
+    }
+
+    if !imported.is_empty() {
+        writer.finish(&imported).await?;
+    }
+    Ok(())
+}
+
+/// Load the IMDb `.tsv.gz` dumps found in `dir` into the database, replacing the current content
+/// of each table. Datasets without a file in `dir` are left untouched.
+/// The import is recorded in `dataset_imports`, which invalidates the graph snapshots.
+pub async fn import_dir(db_pool: &sqlx::PgPool, dir: &Path) -> Result<(), ImportError> {
+    import_datasets(db_pool, dir).await
+}
+
+/// Load the IMDb dumps found in `dir` into the SQLite database, like `import_dir` does for PostgreSQL
+pub async fn import_dir_sqlite(db_pool: &sqlx::SqlitePool, dir: &Path) -> Result<(), ImportError> {
+    import_datasets(db_pool, dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::import::{push_copy_line, ColumnKind, TsvReader};
+
+    const NAME_BASICS: &str =
+        "nconst\tprimaryName\tbirthYear\tdeathYear\tprimaryProfession\tknownForTitles
+nm0000001\tFred Astaire\t1899\t1987\tsoundtrack,actor,miscellaneous\ttt0050419,tt0031983
+nm0000002\tBack\\slash\t\\N\t\\N\t\\N\t\\N
+";
+
+    fn reader(content: &'static str) -> TsvReader {
+        TsvReader::new(Box::new(Cursor::new(content))).unwrap()
+    }
+
+    #[test]
+    fn tsv_reader_nulls_and_arrays() {
+        let mut reader = reader(NAME_BASICS);
+        assert_eq!(reader.column_index("knownForTitles"), Some(5));
+        assert!(reader.column_index("knownfortitles").is_none());
+
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first.get(1), Some("Fred Astaire"));
+        assert_eq!(first.array(5), vec!["tt0050419", "tt0031983"]);
+
+        let second = reader.next().unwrap().unwrap();
+        assert!(second.get(2).is_none());
+        assert!(second.array(5).is_empty());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn copy_line_escapes_values() {
+        let mut reader = reader(NAME_BASICS);
+        let indexes = [
+            (0, ColumnKind::Text),
+            (1, ColumnKind::Text),
+            (3, ColumnKind::Number),
+            (4, ColumnKind::Array),
+        ];
+        let mut buf = String::new();
+        for record in &mut reader {
+            push_copy_line(&mut buf, &record.unwrap(), &indexes);
+        }
+        assert_eq!(
+            buf,
+            "nm0000001\tFred Astaire\t1987\tsoundtrack,actor,miscellaneous\n\
+             nm0000002\tBack\\\\slash\t\\N\t\\N\n"
+        );
+    }
+
+    #[test]
+    fn copy_line_translates_booleans() {
+        let mut reader = reader("tconst\tisAdult\ntt0000001\t0\ntt0000002\t1\n");
+        let indexes = [(0, ColumnKind::Text), (1, ColumnKind::Bool)];
+        let mut buf = String::new();
+        for record in &mut reader {
+            push_copy_line(&mut buf, &record.unwrap(), &indexes);
+        }
+        assert_eq!(buf, "tt0000001\tf\ntt0000002\tt\n");
+    }
+
+    #[test]
+    fn copy_line_null_booleans_are_false() {
+        let mut reader = reader("titleId\tisOriginalTitle\ntt0000001\t\\N\n");
+        let indexes = [(0, ColumnKind::Text), (1, ColumnKind::Bool)];
+        let mut buf = String::new();
+        for record in &mut reader {
+            push_copy_line(&mut buf, &record.unwrap(), &indexes);
+        }
+        assert_eq!(buf, "tt0000001\tf\n");
+    }
+}
@@ -1,207 +1,1224 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use dashmap::DashSet;
 use rayon::prelude::*;
-use rocket::State;
 
-use crate::schemas::TitlePrincipalCache;
+use crate::graph::{ActorGraph, TitleFilter};
+use crate::schemas::SearchStats;
 
-struct NextRoute {
-    success_route: Option<Vec<String>>,
-    next_level: Vec<(String, HashSet<String>)>,
-}
+/// Searches give up beyond this many degrees of separation, unless configured otherwise
+pub const MAX_DEGREE: usize = 10;
+
+/// Frontiers smaller than this are expanded sequentially, even in a parallel search
+const PAR_MIN_FRONTIER: usize = 64;
+
+const UNVISITED: u8 = u8::MAX;
 
 pub struct NameNotFound(pub String);
 
-impl NextRoute {
-    fn new() -> NextRoute {
-        NextRoute {
-            success_route: None,
-            next_level: Vec::from([]),
+pub enum SearchError {
+    NameNotFound(NameNotFound),
+    /// The search ran out of time or names to visit, or was cancelled, after exploring this much
+    OutOfBudget(SearchStats),
+}
+
+/// The shortest routes found by a search
+pub struct Routes {
+    pub routes: Vec<Vec<String>>,
+    /// there are no routes within the maximum degree, but the names may be connected beyond it
+    pub depth_limit_reached: bool,
+}
+
+impl From<NameNotFound> for SearchError {
+    fn from(err: NameNotFound) -> SearchError {
+        SearchError::NameNotFound(err)
+    }
+}
+
+/// Bounds the work of a search: a deadline, a number of names to visit, and cancellation.
+/// Shared with the threads of a parallel search, which stop expanding once it is exhausted.
+pub struct SearchBudget {
+    start: Instant,
+    timeout: Duration,
+    max_visits: usize,
+    visits: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl SearchBudget {
+    pub fn new(timeout: Duration, max_visits: usize) -> SearchBudget {
+        SearchBudget {
+            start: Instant::now(),
+            timeout,
+            max_visits,
+            visits: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
         }
     }
 
-    fn found(route: Vec<String>) -> NextRoute {
-        NextRoute {
-            success_route: Some(route),
-            next_level: Vec::from([]),
+    #[cfg(test)]
+    pub fn unlimited() -> SearchBudget {
+        SearchBudget::new(Duration::MAX, usize::MAX)
+    }
+
+    /// Stop the search at the next name it expands, e.g. because nobody waits for the result anymore
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn spend(&self, visits: usize) {
+        self.visits.fetch_add(visits, Ordering::Relaxed);
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.visits.load(Ordering::Relaxed) >= self.max_visits
+            || self.start.elapsed() >= self.timeout
+    }
+
+    fn stats(&self, levels: usize) -> SearchStats {
+        SearchStats {
+            levels,
+            names_visited: self.visits.load(Ordering::Relaxed),
+            elapsed: self.start.elapsed(),
         }
     }
+}
 
-    fn search_further(next_level: Vec<(String, HashSet<String>)>) -> NextRoute {
-        NextRoute {
-            success_route: None,
-            next_level: next_level,
+/// The state of one end of a bidirectional breadth-first search.
+/// Only the depth of the visited names is kept, routes are recovered from the depths.
+struct Side {
+    depths: Vec<AtomicU8>,
+    visited_titles: Vec<AtomicU64>,
+    frontier: Vec<u32>,
+    depth: u8,
+}
+
+impl Side {
+    fn new(graph: &ActorGraph, root: u32) -> Side {
+        let depths: Vec<AtomicU8> = (0..graph.name_count())
+            .map(|_| AtomicU8::new(UNVISITED))
+            .collect();
+        depths[root as usize].store(0, Ordering::Relaxed);
+        Side {
+            depths,
+            visited_titles: (0..graph.title_count().div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            frontier: vec![root],
+            depth: 0,
+        }
+    }
+
+    fn depth_of(&self, name: u32) -> Option<u8> {
+        let depth = self.depths[name as usize].load(Ordering::Relaxed);
+        if depth == UNVISITED {
+            None
+        } else {
+            Some(depth)
         }
     }
+
+    /// Returns true if the title was not visited before
+    fn visit_title(&self, title: u32) -> bool {
+        let bit = 1u64 << (title % 64);
+        self.visited_titles[title as usize / 64].fetch_or(bit, Ordering::Relaxed) & bit == 0
+    }
+
+    /// Returns true if the name was not visited before
+    fn visit_name(&self, name: u32, depth: u8) -> bool {
+        self.depths[name as usize]
+            .compare_exchange(UNVISITED, depth, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
 }
 
-fn search_names(
-    cache: &State<TitlePrincipalCache>,
-    ignored_names: &DashSet<String>,
-    tconst: &str,
-    nconst2: &str,
-) -> (bool, HashSet<String>) {
-    let mut names_to_visit: HashSet<String> = HashSet::new();
-    if let Some(names) = cache.t_to_p(tconst) {
-        for nconst_i in names.value() {
-            if nconst_i == nconst2 {
-                return (true, names_to_visit);
-            } else if !ignored_names.contains(nconst_i) {
-                names_to_visit.insert(nconst_i.to_string());
-                ignored_names.insert(nconst_i.to_string());
+/// The names reached by expanding part of a frontier, and those of them which were visited by the other side.
+/// Names of the frontier are skipped once the budget is exhausted.
+#[derive(Default)]
+struct Expansion {
+    next_frontier: Vec<u32>,
+    meetings: Vec<u32>,
+    skipped: bool,
+}
+
+impl Expansion {
+    fn merge(mut self, other: Expansion) -> Expansion {
+        self.next_frontier.extend(other.next_frontier);
+        self.meetings.extend(other.meetings);
+        self.skipped |= other.skipped;
+        self
+    }
+}
+
+fn expand_name(
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    budget: &SearchBudget,
+    side: &Side,
+    other: Option<&Side>,
+    name: u32,
+    expansion: &mut Expansion,
+) {
+    if budget.is_exhausted() {
+        expansion.skipped = true;
+        return;
+    }
+    let reached = expansion.next_frontier.len();
+    for title in graph.filtered_titles_of(filter, name) {
+        if side.visit_title(title) {
+            for costar in graph.filtered_names_of(filter, title) {
+                if side.visit_name(costar, side.depth + 1) {
+                    expansion.next_frontier.push(costar);
+                    if other.is_some_and(|other| other.depth_of(costar).is_some()) {
+                        expansion.meetings.push(costar);
+                    }
+                }
             }
         }
     }
-    (false, names_to_visit)
+    budget.spend(expansion.next_frontier.len() - reached);
 }
 
-fn search_route(
-    cache: &State<TitlePrincipalCache>,
-    ignored_titles: &DashSet<String>,
-    ignored_names: &DashSet<String>,
-    route: &str,
-    names_to_visit: &HashSet<String>,
-    nconst2: &str,
-) -> Result<NextRoute, NameNotFound> {
-    let mut next_level: Vec<(String, HashSet<String>)> = Vec::new();
-
-    for nconst in names_to_visit {
-        if let Some(titles) = cache.p_to_t(nconst) {
-            for tconst in titles.value() {
-                if !ignored_titles.contains(tconst) {
-                    ignored_titles.insert(tconst.to_string());
-
-                    let (success, names_to_visit2) =
-                        search_names(cache, ignored_names, &tconst, nconst2);
-                    if success {
-                        let mut route2: Vec<String> = if route == "" {
-                            Vec::new()
-                        } else {
-                            route
-                                .split_whitespace()
-                                .map(|x| x.to_string())
-                                .collect::<Vec<String>>()
-                        };
-                        route2.append(&mut vec![
-                            nconst.to_string(),
-                            tconst.to_string(),
-                            nconst2.to_string(),
-                        ]);
-                        return Ok(NextRoute::found(route2));
-                    } else {
-                        let route2: String = format!("{} {} {}", route, nconst, tconst);
-                        // visited_names.extend(names_to_visit.clone());
-                        next_level.push((route2, names_to_visit2));
+/// Expand the frontier of `side` by one degree. Returns the names where it met `other`, if there is one,
+/// or `None` if the budget ran out before the whole frontier was expanded.
+fn expand_side(
+    do_parallel: bool,
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    budget: &SearchBudget,
+    side: &mut Side,
+    other: Option<&Side>,
+) -> Option<Vec<u32>> {
+    let this: &Side = side;
+    let expansion = if do_parallel && this.frontier.len() >= PAR_MIN_FRONTIER {
+        this.frontier
+            .par_iter()
+            .fold(Expansion::default, |mut expansion, name| {
+                expand_name(graph, filter, budget, this, other, *name, &mut expansion);
+                expansion
+            })
+            .reduce(Expansion::default, Expansion::merge)
+    } else {
+        let mut expansion = Expansion::default();
+        for name in &this.frontier {
+            expand_name(graph, filter, budget, this, other, *name, &mut expansion);
+        }
+        expansion
+    };
+
+    if expansion.skipped {
+        return None;
+    }
+    side.depth += 1;
+    side.frontier = expansion.next_frontier;
+    Some(expansion.meetings)
+}
+
+/// The names of the forward side that are on a shortest route to one of the `meetings`
+fn names_on_routes(
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    forward: &Side,
+    meetings: &[u32],
+) -> HashSet<u32> {
+    let mut on_routes: HashSet<u32> = meetings.iter().copied().collect();
+    let mut layer: Vec<u32> = meetings.to_vec();
+    let mut depth = forward.depth;
+    while depth > 0 {
+        let mut previous: Vec<u32> = Vec::new();
+        for name in &layer {
+            for title in graph.filtered_titles_of(filter, *name) {
+                for costar in graph.filtered_names_of(filter, title) {
+                    if forward.depth_of(costar) == Some(depth - 1) && on_routes.insert(costar) {
+                        previous.push(costar);
                     }
                 }
             }
-        } else {
-            return Err(NameNotFound(nconst.to_string()));
         }
+        layer = previous;
+        depth -= 1;
     }
-
-    Ok(NextRoute::search_further(next_level))
+    on_routes
 }
 
-fn par_search_titles(
-    cache: &State<TitlePrincipalCache>,
-    visited_titles: &mut DashSet<String>,
-    visited_names: &mut DashSet<String>,
-    this_level: &Vec<(String, HashSet<String>)>,
-    nconst2: &str,
-    level: usize,
-) -> Result<Vec<String>, NameNotFound> {
-    let mut next_level: Vec<(String, HashSet<String>)> = Vec::new();
-    let batch_size = 1000;
+/// Enumerates the shortest routes in lexicographic order of their ids, up to a limit
+struct RouteCollector<'a> {
+    graph: &'a ActorGraph,
+    filter: &'a TitleFilter,
+    forward: &'a Side,
+    backward: &'a Side,
+    on_routes: HashSet<u32>,
+    route: Vec<u32>,
+    routes: Vec<Vec<String>>,
+    max_routes: usize,
+}
 
-    for chunk in this_level.chunks(batch_size) {
-        let next_route_result = chunk
-            .par_iter()
-            .map(|(route, names)| {
-                let next_route =
-                    search_route(cache, visited_titles, visited_names, route, names, nconst2);
-                next_route
-            })
-            .try_reduce(
-                || NextRoute::new(),
-                |mut all_routes, next_route| {
-                    all_routes.next_level.extend(next_route.next_level);
-                    Ok(NextRoute {
-                        success_route: all_routes
-                            .success_route
-                            .clone()
-                            .or(next_route.success_route),
-                        next_level: all_routes.next_level,
-                    })
-                },
-            );
-        match next_route_result {
-            Ok(next_route) => {
-                if let Some(route) = next_route.success_route {
-                    return Ok(route);
-                } else {
-                    next_level.extend(next_route.next_level);
+impl RouteCollector<'_> {
+    /// Extend the current route from `name`, first on the forward side up to a meeting name,
+    /// then on the backward side, down to its root
+    fn collect(&mut self, name: u32) {
+        self.route.push(name);
+        let forward_depth = self.forward.depth_of(name).unwrap_or(UNVISITED);
+        let backward_depth = self.backward.depth_of(name).unwrap_or(UNVISITED);
+        if backward_depth == 0 {
+            self.routes.push(self.route_ids());
+        } else {
+            let graph = self.graph;
+            for title in graph.filtered_titles_of(self.filter, name) {
+                for next in graph.filtered_names_of(self.filter, title) {
+                    if self.routes.len() >= self.max_routes {
+                        self.route.pop();
+                        return;
+                    }
+                    let is_next = if forward_depth < self.forward.depth {
+                        self.forward.depth_of(next) == Some(forward_depth + 1)
+                            && self.on_routes.contains(&next)
+                    } else {
+                        self.backward.depth_of(next) == Some(backward_depth - 1)
+                    };
+                    if is_next {
+                        self.route.push(title);
+                        self.collect(next);
+                        self.route.pop();
+                    }
                 }
             }
-            Err(err) => return Err(err),
         }
+        self.route.pop();
     }
 
-    if level > 9 || next_level.is_empty() {
-        Ok(vec![])
-    } else {
-        par_search_titles(
-            cache,
-            visited_titles,
-            visited_names,
-            &next_level,
-            nconst2,
-            level + 1,
-        )
+    fn route_ids(&self) -> Vec<String> {
+        self.route
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                if i % 2 == 0 {
+                    self.graph.nconst(*id)
+                } else {
+                    self.graph.tconst(*id)
+                }
+            })
+            .collect()
     }
 }
 
-pub fn search_titles(
+/// Search the shortest routes between 2 names with a bidirectional breadth-first search,
+/// always expanding the smaller frontier. A route alternates names and titles, starting with `nconst1`
+/// and ending with `nconst2`. Only titles accepted by `filter` connect names, through the roles it accepts.
+/// At most `max_routes` routes are returned, ordered by their ids.
+/// There are no routes if the names are not connected within `max_degree` degrees,
+/// and the result tells whether the search stopped at that limit or ran out of names to visit.
+/// The search fails with its statistics when it exhausts the `budget` before finding the routes.
+#[allow(clippy::too_many_arguments)]
+pub fn search(
     do_parallel: bool,
-    cache: &State<TitlePrincipalCache>,
-    visited_titles: &mut DashSet<String>,
-    visited_names: &mut DashSet<String>,
-    this_level: &Vec<(String, HashSet<String>)>,
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    nconst1: &str,
     nconst2: &str,
-    level: usize,
-) -> Result<Vec<String>, NameNotFound> {
-    let mut next_level: Vec<(String, HashSet<String>)> = Vec::new();
-
-    for (route, names) in this_level {
-        let next_route = search_route(cache, visited_titles, visited_names, route, names, nconst2)?;
-        if let Some(success_route) = next_route.success_route {
-            return Ok(success_route);
+    max_routes: usize,
+    max_degree: usize,
+    budget: &SearchBudget,
+) -> Result<Routes, SearchError> {
+    let name1 = graph
+        .name_id(nconst1)
+        .ok_or_else(|| NameNotFound(nconst1.to_string()))?;
+    let name2 = graph
+        .name_id(nconst2)
+        .ok_or_else(|| NameNotFound(nconst2.to_string()))?;
+    if name1 == name2 {
+        return Ok(Routes {
+            routes: vec![vec![nconst1.to_string()]],
+            depth_limit_reached: false,
+        });
+    }
+
+    // the depths of both sides are kept below UNVISITED
+    let max_degree = max_degree.min(UNVISITED as usize - 1);
+    let mut forward = Side::new(graph, name1);
+    let mut backward = Side::new(graph, name2);
+
+    while ((forward.depth + backward.depth) as usize) < max_degree
+        && !forward.frontier.is_empty()
+        && !backward.frontier.is_empty()
+    {
+        let expand_forward = forward.frontier.len() <= backward.frontier.len();
+        let meetings = if expand_forward {
+            expand_side(
+                do_parallel,
+                graph,
+                filter,
+                budget,
+                &mut forward,
+                Some(&backward),
+            )
         } else {
-            next_level.extend(next_route.next_level);
+            expand_side(
+                do_parallel,
+                graph,
+                filter,
+                budget,
+                &mut backward,
+                Some(&forward),
+            )
+        };
+        // a partly expanded level may miss the nearest meetings, but a level completed
+        // on the last of the budget has them
+        let Some(mut meetings) = meetings else {
+            let levels = (forward.depth + backward.depth) as usize;
+            return Err(SearchError::OutOfBudget(budget.stats(levels)));
+        };
+
+        if !meetings.is_empty() {
+            // only the meetings on the shortest routes are kept, and the forward side
+            // must end where those meetings are, for the route enumeration
+            let meeting_depth =
+                |name: &u32| forward.depth_of(*name).unwrap() + backward.depth_of(*name).unwrap();
+            let shortest = meetings.iter().map(meeting_depth).min().unwrap();
+            meetings.retain(|name| meeting_depth(name) == shortest);
+            if !expand_forward {
+                forward.depth = forward.depth_of(meetings[0]).unwrap();
+            }
+
+            let mut collector = RouteCollector {
+                graph,
+                filter,
+                forward: &forward,
+                backward: &backward,
+                on_routes: names_on_routes(graph, filter, &forward, &meetings),
+                route: Vec::new(),
+                routes: Vec::new(),
+                max_routes,
+            };
+            collector.collect(name1);
+            return Ok(Routes {
+                routes: collector.routes,
+                depth_limit_reached: false,
+            });
         }
     }
 
-    if level > 9 || next_level.is_empty() {
-        Ok(vec![])
-    } else if do_parallel && level > 1 {
-        par_search_titles(
-            cache,
-            visited_titles,
-            visited_names,
-            &next_level,
-            nconst2,
-            level + 1,
+    Ok(Routes {
+        routes: vec![],
+        depth_limit_reached: !forward.frontier.is_empty() && !backward.frontier.is_empty(),
+    })
+}
+
+/// The names reached from one name, by degree of separation
+pub struct Reach {
+    /// the number of names at each degree, starting with the name itself at degree 0
+    pub counts: Vec<usize>,
+    /// the ids of the names at the largest degree, in id order
+    pub farthest: Vec<String>,
+}
+
+/// Visit every name connected to `nconst` with a breadth-first search, through the titles and roles accepted
+/// by `filter`. At most `max_farthest` of the farthest names are returned.
+/// The search fails with its statistics when it exhausts the `budget`.
+pub fn reach(
+    do_parallel: bool,
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    nconst: &str,
+    max_farthest: usize,
+    budget: &SearchBudget,
+) -> Result<Reach, SearchError> {
+    let name = graph
+        .name_id(nconst)
+        .ok_or_else(|| NameNotFound(nconst.to_string()))?;
+    let mut side = Side::new(graph, name);
+    let mut counts = vec![1];
+    while !side.frontier.is_empty() && side.depth < UNVISITED - 1 {
+        if expand_side(do_parallel, graph, filter, budget, &mut side, None).is_none() {
+            return Err(SearchError::OutOfBudget(budget.stats(side.depth as usize)));
+        }
+        if !side.frontier.is_empty() {
+            counts.push(side.frontier.len());
+        }
+    }
+
+    // names are numbered in id order, scanning them sorts the farthest ones
+    let max_depth = (counts.len() - 1) as u8;
+    let farthest = (0..graph.name_count() as u32)
+        .filter(|name| side.depth_of(*name) == Some(max_depth))
+        .take(max_farthest)
+        .map(|name| graph.nconst(name))
+        .collect();
+    Ok(Reach { counts, farthest })
+}
+
+/// The names within some degrees of one name, and the titles connecting them
+pub struct EgoNetwork {
+    /// the ids of the names with their degree, by degree then id
+    pub names: Vec<(String, usize)>,
+    /// the ids of the titles with the names they connect, in id order
+    pub titles: Vec<(String, Vec<String>)>,
+    /// the names were limited to `max_names`, some names within `max_degree` are missing
+    pub truncated: bool,
+}
+
+/// Collect the names within `max_degree` degrees of `nconst` with a breadth-first search, nearest first,
+/// and at most `max_names` of them. The titles accepted by `filter` that connect at least 2 of the names
+/// are the edges of the network, even if the search did not go through them.
+/// The search fails with its statistics when it exhausts the `budget`.
+pub fn ego_network(
+    graph: &ActorGraph,
+    filter: &TitleFilter,
+    nconst: &str,
+    max_degree: usize,
+    max_names: usize,
+    budget: &SearchBudget,
+) -> Result<EgoNetwork, SearchError> {
+    let center = graph
+        .name_id(nconst)
+        .ok_or_else(|| NameNotFound(nconst.to_string()))?;
+    // an ego network is small compared to the graph, the degrees are kept in a map rather than by name id
+    let mut degrees: HashMap<u32, usize> = HashMap::from([(center, 0)]);
+    let mut frontier = vec![center];
+    let mut truncated = false;
+    'levels: for degree in 1..=max_degree {
+        let mut next_frontier = Vec::new();
+        for name in &frontier {
+            if budget.is_exhausted() {
+                return Err(SearchError::OutOfBudget(budget.stats(degree - 1)));
+            }
+            let reached = next_frontier.len();
+            for title in graph.filtered_titles_of(filter, *name) {
+                for costar in graph.filtered_names_of(filter, title) {
+                    if !degrees.contains_key(&costar) {
+                        if degrees.len() >= max_names {
+                            truncated = true;
+                            break 'levels;
+                        }
+                        degrees.insert(costar, degree);
+                        next_frontier.push(costar);
+                    }
+                }
+            }
+            budget.spend(next_frontier.len() - reached);
+        }
+        // the names of the next level are expanded in id order, so a truncated network is reproducible
+        next_frontier.sort_unstable();
+        frontier = next_frontier;
+    }
+
+    let mut names: Vec<(u32, usize)> = degrees
+        .iter()
+        .map(|(name, degree)| (*name, *degree))
+        .collect();
+    names.sort_unstable_by_key(|(name, degree)| (*degree, *name));
+    let mut titles: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (name, _) in &names {
+        if budget.is_exhausted() {
+            return Err(SearchError::OutOfBudget(budget.stats(max_degree)));
+        }
+        for title in graph.filtered_titles_of(filter, *name) {
+            titles.entry(title).or_insert_with(|| {
+                graph
+                    .filtered_names_of(filter, title)
+                    .filter(|costar| degrees.contains_key(costar))
+                    .collect()
+            });
+        }
+    }
+    let mut titles: Vec<(u32, Vec<u32>)> = titles
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect();
+    titles.sort_unstable();
+
+    Ok(EgoNetwork {
+        names: names
+            .into_iter()
+            .map(|(name, degree)| (graph.nconst(name), degree))
+            .collect(),
+        titles: titles
+            .into_iter()
+            .map(|(title, names)| {
+                let nconsts = names.into_iter().map(|name| graph.nconst(name)).collect();
+                (graph.tconst(title), nconsts)
+            })
+            .collect(),
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{ActorGraph, GraphBuilder, TitleFilter};
+    use crate::kevinbacon::{
+        ego_network, reach, search, NameNotFound, SearchBudget, SearchError, MAX_DEGREE,
+    };
+    use crate::schemas::TitleFilterParams;
+    use std::time::Duration;
+
+    /// nm1 - nm2 - nm3 - nm4, nm5 - nm6 disconnected
+    fn chain_graph() -> ActorGraph {
+        let mut builder = GraphBuilder::new();
+        for (tconst, nconst) in [
+            ("tt0000001", "nm0000001"),
+            ("tt0000001", "nm0000002"),
+            ("tt0000002", "nm0000002"),
+            ("tt0000002", "nm0000003"),
+            ("tt0000003", "nm0000003"),
+            ("tt0000003", "nm0000004"),
+            ("tt0000004", "nm0000001"),
+            ("tt0000004", "nm0000009"),
+            ("tt0000005", "nm0000005"),
+            ("tt0000005", "nm0000006"),
+        ] {
+            builder.add(tconst, nconst, "actor");
+        }
+        builder.build()
+    }
+
+    fn route(items: &[&str]) -> Vec<String> {
+        items.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn search_finds_shortest_route_in_both_directions() {
+        let graph = chain_graph();
+        for parallel in [false, true] {
+            assert_eq!(
+                search(
+                    parallel,
+                    &graph,
+                    &TitleFilter::default(),
+                    "nm0000001",
+                    "nm0000004",
+                    10,
+                    MAX_DEGREE,
+                    &SearchBudget::unlimited()
+                )
+                .ok()
+                .unwrap()
+                .routes,
+                vec![route(&[
+                    "nm0000001",
+                    "tt0000001",
+                    "nm0000002",
+                    "tt0000002",
+                    "nm0000003",
+                    "tt0000003",
+                    "nm0000004"
+                ])]
+            );
+            assert_eq!(
+                search(
+                    parallel,
+                    &graph,
+                    &TitleFilter::default(),
+                    "nm0000004",
+                    "nm0000001",
+                    10,
+                    MAX_DEGREE,
+                    &SearchBudget::unlimited()
+                )
+                .ok()
+                .unwrap()
+                .routes,
+                vec![route(&[
+                    "nm0000004",
+                    "tt0000003",
+                    "nm0000003",
+                    "tt0000002",
+                    "nm0000002",
+                    "tt0000001",
+                    "nm0000001"
+                ])]
+            );
+        }
+    }
+
+    #[test]
+    fn search_direct_and_same_name() {
+        let graph = chain_graph();
+        assert_eq!(
+            search(
+                false,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000002",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::unlimited()
+            )
+            .ok()
+            .unwrap()
+            .routes,
+            vec![route(&["nm0000001", "tt0000001", "nm0000002"])]
+        );
+        assert_eq!(
+            search(
+                false,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000001",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::unlimited()
+            )
+            .ok()
+            .unwrap()
+            .routes,
+            vec![route(&["nm0000001"])]
+        );
+    }
+
+    #[test]
+    fn search_disconnected_and_unknown() {
+        let graph = chain_graph();
+        let result = search(
+            false,
+            &graph,
+            &TitleFilter::default(),
+            "nm0000001",
+            "nm0000006",
+            10,
+            MAX_DEGREE,
+            &SearchBudget::unlimited(),
         )
-    } else {
-        search_titles(
-            do_parallel,
-            cache,
-            visited_titles,
-            visited_names,
-            &next_level,
-            nconst2,
-            level + 1,
+        .ok()
+        .unwrap();
+        assert!(result.routes.is_empty());
+        assert!(!result.depth_limit_reached);
+        assert_eq!(
+            search(
+                false,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000099",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::unlimited()
+            )
+            .err()
+            .map(|err| match err {
+                SearchError::NameNotFound(NameNotFound(nconst)) => nconst,
+                SearchError::OutOfBudget(_) => String::new(),
+            }),
+            Some("nm0000099".to_string())
+        );
+    }
+
+    #[test]
+    fn search_depth_limit() {
+        let graph = chain_graph();
+        for (max_degree, degree, depth_limit_reached) in [(2, None, true), (3, Some(3), false)] {
+            let result = search(
+                true,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000004",
+                10,
+                max_degree,
+                &SearchBudget::unlimited(),
+            )
+            .ok()
+            .unwrap();
+            assert_eq!(result.routes.first().map(|route| route.len() / 2), degree);
+            assert_eq!(result.depth_limit_reached, depth_limit_reached);
+        }
+    }
+
+    #[test]
+    fn reach_counts_names_by_degree() {
+        let graph = chain_graph();
+        for parallel in [false, true] {
+            let reach = reach(
+                parallel,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                10,
+                &SearchBudget::unlimited(),
+            )
+            .ok()
+            .unwrap();
+            assert_eq!(reach.counts, vec![1, 2, 1, 1]);
+            assert_eq!(reach.farthest, route(&["nm0000004"]));
+        }
+        let reach = reach(
+            false,
+            &graph,
+            &TitleFilter::default(),
+            "nm0000002",
+            1,
+            &SearchBudget::unlimited(),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(reach.counts, vec![1, 2, 2]);
+        assert_eq!(reach.farthest, route(&["nm0000004"]));
+    }
+
+    #[test]
+    fn ego_network_within_degrees() {
+        let graph = chain_graph();
+        let network = ego_network(
+            &graph,
+            &TitleFilter::default(),
+            "nm0000002",
+            1,
+            100,
+            &SearchBudget::unlimited(),
         )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            network.names,
+            vec![
+                ("nm0000002".to_string(), 0),
+                ("nm0000001".to_string(), 1),
+                ("nm0000003".to_string(), 1)
+            ]
+        );
+        // tt4 connects nm1 to nm9 only, who is not in the network
+        assert_eq!(
+            network.titles,
+            vec![
+                ("tt0000001".to_string(), route(&["nm0000001", "nm0000002"])),
+                ("tt0000002".to_string(), route(&["nm0000002", "nm0000003"]))
+            ]
+        );
+        assert!(!network.truncated);
+
+        let network = ego_network(
+            &graph,
+            &TitleFilter::default(),
+            "nm0000002",
+            2,
+            4,
+            &SearchBudget::unlimited(),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(network.names.len(), 4);
+        // nm1 is expanded before nm3, reaching nm9 before nm4
+        assert_eq!(network.names[3], ("nm0000009".to_string(), 2));
+        assert!(network.truncated);
+
+        let cancelled = SearchBudget::unlimited();
+        cancelled.cancel();
+        assert!(matches!(
+            ego_network(
+                &graph,
+                &TitleFilter::default(),
+                "nm0000002",
+                2,
+                4,
+                &cancelled
+            ),
+            Err(SearchError::OutOfBudget(_))
+        ));
+    }
+
+    #[test]
+    fn search_out_of_budget() {
+        let graph = chain_graph();
+        for parallel in [false, true] {
+            match search(
+                parallel,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000004",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::new(Duration::MAX, 1),
+            ) {
+                Err(SearchError::OutOfBudget(stats)) => {
+                    assert_eq!(stats.levels, 1);
+                    assert_eq!(stats.names_visited, 2);
+                }
+                other => panic!(
+                    "unexpected search result {:?}",
+                    other.ok().map(|result| result.routes)
+                ),
+            }
+        }
+        // the route is found on the level that spends the last of the budget
+        for parallel in [false, true] {
+            let routes = search(
+                parallel,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000003",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::new(Duration::MAX, 3),
+            )
+            .ok()
+            .map(|result| result.routes);
+            assert_eq!(
+                routes,
+                Some(vec![route(&[
+                    "nm0000001",
+                    "tt0000001",
+                    "nm0000002",
+                    "tt0000002",
+                    "nm0000003"
+                ])])
+            );
+        }
+        let cancelled = SearchBudget::unlimited();
+        cancelled.cancel();
+        assert!(matches!(
+            search(
+                false,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000004",
+                10,
+                MAX_DEGREE,
+                &cancelled
+            ),
+            Err(SearchError::OutOfBudget(_))
+        ));
+    }
+
+    /// nm1 and nm4 are connected through nm2 and nm3, and nm1 - nm2 through 2 titles
+    fn diamond_graph() -> ActorGraph {
+        let mut builder = GraphBuilder::new();
+        for (tconst, nconst) in [
+            ("tt0000001", "nm0000001"),
+            ("tt0000001", "nm0000002"),
+            ("tt0000002", "nm0000001"),
+            ("tt0000002", "nm0000003"),
+            ("tt0000003", "nm0000002"),
+            ("tt0000003", "nm0000004"),
+            ("tt0000004", "nm0000003"),
+            ("tt0000004", "nm0000004"),
+            ("tt0000005", "nm0000002"),
+            ("tt0000005", "nm0000001"),
+            ("tt0000006", "nm0000004"),
+            ("tt0000006", "nm0000005"),
+        ] {
+            builder.add(tconst, nconst, "actor");
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn search_all_shortest_routes_in_order() {
+        let graph = diamond_graph();
+        let all = vec![
+            route(&[
+                "nm0000001",
+                "tt0000001",
+                "nm0000002",
+                "tt0000003",
+                "nm0000004",
+            ]),
+            route(&[
+                "nm0000001",
+                "tt0000002",
+                "nm0000003",
+                "tt0000004",
+                "nm0000004",
+            ]),
+            route(&[
+                "nm0000001",
+                "tt0000005",
+                "nm0000002",
+                "tt0000003",
+                "nm0000004",
+            ]),
+        ];
+        for parallel in [false, true] {
+            assert_eq!(
+                search(
+                    parallel,
+                    &graph,
+                    &TitleFilter::default(),
+                    "nm0000001",
+                    "nm0000004",
+                    10,
+                    MAX_DEGREE,
+                    &SearchBudget::unlimited()
+                )
+                .ok()
+                .unwrap()
+                .routes,
+                all
+            );
+            assert_eq!(
+                search(
+                    parallel,
+                    &graph,
+                    &TitleFilter::default(),
+                    "nm0000001",
+                    "nm0000004",
+                    2,
+                    MAX_DEGREE,
+                    &SearchBudget::unlimited()
+                )
+                .ok()
+                .unwrap()
+                .routes,
+                all[..2]
+            );
+        }
+        // the backward side is expanded first from nm5, which has the smaller frontier
+        assert_eq!(
+            search(
+                false,
+                &graph,
+                &TitleFilter::default(),
+                "nm0000001",
+                "nm0000005",
+                10,
+                MAX_DEGREE,
+                &SearchBudget::unlimited()
+            )
+            .ok()
+            .unwrap()
+            .routes
+            .len(),
+            3
+        );
+    }
+
+    /// Distances from `name` by a plain breadth-first search over the graph
+    fn bfs_distances(graph: &ActorGraph, name: u32) -> Vec<Option<usize>> {
+        let mut distances: Vec<Option<usize>> = vec![None; graph.name_count()];
+        distances[name as usize] = Some(0);
+        let mut layer = vec![name];
+        let mut depth = 0;
+        while !layer.is_empty() {
+            depth += 1;
+            let mut next = Vec::new();
+            for n in layer {
+                for t in graph.titles_of(n) {
+                    for costar in graph.names_of(*t) {
+                        if distances[*costar as usize].is_none() {
+                            distances[*costar as usize] = Some(depth);
+                            next.push(*costar);
+                        }
+                    }
+                }
+            }
+            layer = next;
+        }
+        distances
+    }
+
+    #[test]
+    fn search_matches_plain_bfs_on_random_graph() {
+        let mut seed: u64 = 42;
+        let mut random = |max: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % max
+        };
+        let mut builder = GraphBuilder::new();
+        for title in 1..=150 {
+            for _ in 0..2 + random(3) {
+                let name = 1 + random(200);
+                builder.add(
+                    &format!("tt{:07}", title),
+                    &format!("nm{:07}", name),
+                    "actor",
+                );
+            }
+        }
+        let graph = builder.build();
+
+        for name1 in (0..graph.name_count() as u32).step_by(7) {
+            let distances = bfs_distances(&graph, name1);
+            for name2 in (0..graph.name_count() as u32).step_by(5) {
+                let nconst1 = graph.nconst(name1);
+                let nconst2 = graph.nconst(name2);
+                let routes = search(
+                    name1 % 2 == 0,
+                    &graph,
+                    &TitleFilter::default(),
+                    &nconst1,
+                    &nconst2,
+                    20,
+                    MAX_DEGREE,
+                    &SearchBudget::unlimited(),
+                )
+                .ok()
+                .unwrap()
+                .routes;
+                match distances[name2 as usize] {
+                    Some(distance) if distance <= 10 => {
+                        assert!(!routes.is_empty());
+                        for route in &routes {
+                            assert_eq!(route.len(), distance * 2 + 1);
+                            assert_eq!(route[0], nconst1);
+                            assert_eq!(route[route.len() - 1], nconst2);
+                            for step in route.windows(3).step_by(2) {
+                                let title = graph
+                                    .titles_of(graph.name_id(&step[0]).unwrap())
+                                    .iter()
+                                    .find(|t| graph.tconst(**t) == step[1])
+                                    .unwrap();
+                                let name = graph.name_id(&step[2]).unwrap();
+                                assert!(graph.names_of(*title).contains(&name));
+                            }
+                        }
+                        assert!(routes.windows(2).all(|w| w[0] < w[1]));
+                    }
+                    _ => assert!(routes.is_empty()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_only_through_accepted_titles() {
+        let mut builder = GraphBuilder::new();
+        for (tconst, title_type, start_year, genres, num_votes) in [
+            ("tt0000001", "movie", 1990, "Drama", 5000),
+            ("tt0000002", "movie", 2005, "Comedy,Drama", 200),
+            ("tt0000003", "movie", 2010, "Drama", 8000),
+            ("tt0000007", "tvEpisode", 2010, "Comedy", 10),
+        ] {
+            builder.add_title(
+                tconst,
+                Some(title_type),
+                Some(start_year),
+                Some(genres),
+                Some(num_votes),
+                Some(7.5),
+            );
+        }
+        for (tconst, nconst) in [
+            ("tt0000001", "nm0000001"),
+            ("tt0000001", "nm0000002"),
+            ("tt0000002", "nm0000002"),
+            ("tt0000002", "nm0000003"),
+            ("tt0000003", "nm0000003"),
+            ("tt0000003", "nm0000004"),
+            ("tt0000007", "nm0000001"),
+            ("tt0000007", "nm0000004"),
+        ] {
+            builder.add(tconst, nconst, "actor");
+        }
+        let graph = builder.build();
+        let search_with = |params: TitleFilterParams| {
+            let filter = graph.title_filter(&params).unwrap();
+            search(
+                false,
+                &graph,
+                &filter,
+                "nm0000001",
+                "nm0000004",
+                1,
+                MAX_DEGREE,
+                &SearchBudget::unlimited(),
+            )
+            .ok()
+            .unwrap()
+            .routes
+        };
+
+        assert_eq!(
+            search_with(TitleFilterParams::default()),
+            vec![route(&["nm0000001", "tt0000007", "nm0000004"])]
+        );
+        let movies = TitleFilterParams {
+            titletype: Some("movie".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search_with(movies)[0].len(), 7);
+        let drama = TitleFilterParams {
+            genres: Some("Drama".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search_with(drama)[0].len(), 7);
+        let popular = TitleFilterParams {
+            min_numvotes: Some(1000),
+            ..Default::default()
+        };
+        assert!(search_with(popular).is_empty());
+        let recent = TitleFilterParams {
+            startyear_from: Some(2000),
+            ..Default::default()
+        };
+        assert_eq!(search_with(recent)[0].len(), 3);
+
+        let unknown = TitleFilterParams {
+            genres: Some("Western".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            graph.title_filter(&unknown).unwrap_err().body().message,
+            "Unknown genre Western"
+        );
+    }
+
+    #[test]
+    fn search_through_chosen_categories() {
+        let mut builder = GraphBuilder::new();
+        for (tconst, nconst, category) in [
+            ("tt0000001", "nm0000001", "actor"),
+            ("tt0000001", "nm0000002", "director"),
+            ("tt0000002", "nm0000002", "director"),
+            ("tt0000002", "nm0000003", "actress"),
+            ("tt0000003", "nm0000001", "actor"),
+            ("tt0000003", "nm0000004", "actor"),
+            ("tt0000004", "nm0000004", "actor"),
+            ("tt0000004", "nm0000005", "actor"),
+            ("tt0000005", "nm0000005", "actor"),
+            ("tt0000005", "nm0000003", "actress"),
+        ] {
+            builder.add(tconst, nconst, category);
+        }
+        let graph = builder.build();
+        let search_with = |categories: Option<&str>| {
+            let params = TitleFilterParams {
+                categories: categories.map(|c| c.to_string()),
+                ..Default::default()
+            };
+            let filter = graph.title_filter(&params).unwrap();
+            search(
+                false,
+                &graph,
+                &filter,
+                "nm0000001",
+                "nm0000003",
+                1,
+                MAX_DEGREE,
+                &SearchBudget::unlimited(),
+            )
+            .ok()
+            .unwrap()
+            .routes
+        };
+
+        assert_eq!(search_with(None)[0].len(), 7);
+        assert_eq!(
+            search_with(Some("director,actor,actress")),
+            vec![route(&[
+                "nm0000001",
+                "tt0000001",
+                "nm0000002",
+                "tt0000002",
+                "nm0000003"
+            ])]
+        );
+        assert!(search_with(Some("actor")).is_empty());
+
+        let unknown = TitleFilterParams {
+            categories: Some("writer".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            graph.title_filter(&unknown).unwrap_err().body().message,
+            "Unknown category writer"
+        );
     }
 }
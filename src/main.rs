@@ -1,190 +1,726 @@
 #[macro_use]
 extern crate rocket;
 
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use dashmap::DashSet;
-use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::Status;
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::futures::Stream;
+use rocket::http::{ContentType, Header};
+use rocket::response::stream::ByteStream;
 use rocket::serde::json::Json;
-use rocket::{Orbit, Rocket, State};
+use rocket::serde::Deserialize;
+use rocket::tokio::task;
+use rocket::{Build, Ignite, Orbit, Phase, Rocket, State};
 
 use rocket_okapi::gen::OpenApiGenerator;
-use rocket_okapi::okapi::schemars;
-use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use rocket_okapi::okapi::openapi3::{ParameterValue, RefOr, Responses};
+use rocket_okapi::okapi::{self, schemars};
+use rocket_okapi::response::OpenApiResponderInner;
 use rocket_okapi::settings::UrlObject;
+use rocket_okapi::util::ensure_status_code_exists;
 use rocket_okapi::{openapi, openapi_get_routes, rapidoc::*, swagger_ui::*};
 
 use rocket_db_pools::sqlx;
 use rocket_db_pools::Database;
 use serde::Serialize;
 
+use crate::error::ApiError;
+use crate::memory::MemoryRepository;
+use crate::repo::{PostgresRepository, Repository};
+use crate::sqlite::SqliteRepository;
+
+mod error;
+mod export;
+mod graph;
+mod import;
 mod kevinbacon;
+mod memory;
+mod migrate;
 mod repo;
 mod schemas;
+mod similarity;
+mod snapshot;
+mod sqlite;
 
 #[derive(Database)]
 #[database("imdb_db")]
 struct DbPool(sqlx::PgPool);
 
-impl<'r> OpenApiFromRequest<'r> for &'r DbPool {
-    fn from_request_input(
-        _gen: &mut OpenApiGenerator,
-        _name: String,
-        _required: bool,
-    ) -> rocket_okapi::Result<RequestHeaderInput> {
-        Ok(RequestHeaderInput::None)
+#[derive(Database)]
+#[database("imdb_sqlite")]
+struct SqliteDbPool(sqlx::SqlitePool);
+
+/// Page size of a title search without limit
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Upper limit of the page size of a title search
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// The limits of the degrees of separation searches, from the `search_timeout_ms`, `search_max_visits`
+/// and `search_max_degree` settings. Requests may only lower them.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct SearchConfig {
+    #[serde(default = "SearchConfig::default_timeout_ms")]
+    search_timeout_ms: u64,
+    #[serde(default = "SearchConfig::default_max_visits")]
+    search_max_visits: usize,
+    #[serde(default = "SearchConfig::default_max_degree")]
+    search_max_degree: usize,
+}
+
+impl SearchConfig {
+    fn default_timeout_ms() -> u64 {
+        10_000
+    }
+
+    fn default_max_visits() -> usize {
+        10_000_000
+    }
+
+    fn default_max_degree() -> usize {
+        kevinbacon::MAX_DEGREE
+    }
+
+    fn max_degree(&self, max_degree: Option<usize>) -> usize {
+        max_degree.map_or(self.search_max_degree, |degree| {
+            degree.clamp(1, self.search_max_degree)
+        })
+    }
+
+    fn budget(
+        &self,
+        timeout_ms: Option<u64>,
+        max_visits: Option<usize>,
+    ) -> kevinbacon::SearchBudget {
+        let timeout_ms =
+            timeout_ms.map_or(self.search_timeout_ms, |ms| ms.min(self.search_timeout_ms));
+        let max_visits = max_visits.map_or(self.search_max_visits, |visits| {
+            visits.min(self.search_max_visits)
+        });
+        kevinbacon::SearchBudget::new(Duration::from_millis(timeout_ms), max_visits)
+    }
+}
+
+/// The size limit of the graph exports served by the API, from the `export_max_nodes` setting.
+/// The export command writes any graph.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ExportConfig {
+    #[serde(default = "ExportConfig::default_max_nodes")]
+    export_max_nodes: usize,
+}
+
+impl ExportConfig {
+    fn default_max_nodes() -> usize {
+        500_000
+    }
+}
+
+/// Cancels a search when the request handler is dropped, e.g. because the client went away
+struct CancelOnDrop(Arc<kevinbacon::SearchBudget>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
     }
 }
 
-/// Search films or other moving pictures by a title fragment
+/// A page of search results, with the total number of results in the `X-Total-Count` header
+#[derive(Responder)]
+struct Page<T> {
+    items: Json<Vec<T>>,
+    total_count: Header<'static>,
+}
+
+impl<T> Page<T> {
+    fn new(items: Vec<T>, total_count: i64) -> Page<T> {
+        Page {
+            items: Json(items),
+            total_count: Header::new("X-Total-Count", total_count.to_string()),
+        }
+    }
+}
+
+/// The title types of a comma-separated titletype parameter, e.g. tvSeries,tvMiniSeries
+fn title_types(titletype: &str) -> Vec<String> {
+    titletype
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+impl<T: Serialize + schemars::JsonSchema + Send> OpenApiResponderInner for Page<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Json::<Vec<T>>::responses(gen)?;
+        if let RefOr::Object(response) = ensure_status_code_exists(&mut responses, 200) {
+            response.headers.insert(
+                "X-Total-Count".to_owned(),
+                RefOr::Object(okapi::openapi3::Header {
+                    description: Some("The total number of results of all pages".to_owned()),
+                    value: ParameterValue::Schema {
+                        style: None,
+                        explode: None,
+                        allow_reserved: false,
+                        schema: gen.json_schema::<i64>(),
+                        example: None,
+                        examples: None,
+                    },
+                    required: true,
+                    deprecated: false,
+                    allow_empty_value: false,
+                    extensions: Default::default(),
+                }),
+            );
+        }
+        Ok(responses)
+    }
+}
+
+/// Search films or other moving pictures by a title fragment.<br/>
+/// <ul>
+/// <li>The fuzzy mode also finds titles with typos or with the words of the fragment in another order,
+/// scored by relevance. Exact titles score highest.</li>
+/// <li>Alternate titles (e.g. localized titles) are searched too, restricted to a region (e.g. FR) or a language (e.g. fr) if these are given.
+/// A title matching through an alternate title reports it as its aka.</li>
+/// <li>The titletype is a comma-separated list of title types, e.g. tvSeries,tvMiniSeries. Movies by default.</li>
+/// <li>Results are sorted by year (default), rating, votes, title or relevance (default of fuzzy searches).</li>
+/// <li>The limit (default 50, at most 500) and offset select a page of the results.</li>
+/// <li>The X-Total-Count header has the number of results of all pages.</li>
+/// </ul>
 #[openapi(tag = "IMDB")]
-#[get("/imdb/title?<title_fragment>")]
+#[get(
+    "/imdb/title?<title_fragment>&<mode>&<region>&<language>&<titletype>&<sort>&<limit>&<offset>"
+)]
+#[allow(clippy::too_many_arguments)]
 async fn titles(
-    db: &DbPool,
+    repository: &State<Repository>,
     title_fragment: &str,
-) -> Result<Json<Vec<schemas::TitleDetails>>, (Status, String)> {
-    let result = repo::titles_by_name(&db.0, title_fragment).await?;
-    Ok(Json(result))
+    mode: Option<schemas::SearchMode>,
+    region: Option<&str>,
+    language: Option<&str>,
+    titletype: Option<&str>,
+    sort: Option<schemas::TitleSort>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Page<schemas::TitleDetails>, ApiError> {
+    let mode = mode.unwrap_or_default();
+    let sort = match (mode, sort) {
+        (schemas::SearchMode::Fuzzy, None) => schemas::TitleSort::Relevance,
+        (schemas::SearchMode::Substring, Some(schemas::TitleSort::Relevance)) => {
+            return Err(ApiError::bad_input(
+                Some("relevance"),
+                "Sorting by relevance needs mode=fuzzy".to_string(),
+            ))
+        }
+        (_, sort) => sort.unwrap_or_default(),
+    };
+    let query = schemas::TitleQuery {
+        fragment: title_fragment.to_string(),
+        mode,
+        // IMDb regions are upper case and languages lower case
+        region: region.map(|region| region.to_uppercase()),
+        language: language.map(|language| language.to_lowercase()),
+        title_types: title_types(titletype.unwrap_or("movie")),
+        sort,
+        limit: limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        offset: offset.unwrap_or(0).max(0),
+    };
+    let (result, total_count) = repository.titles_by_name(&query).await?;
+    Ok(Page::new(result, total_count))
+}
+
+/// A title by id, with its rating, principals in billing order, directors and writers, alternate titles
+/// and, for episodes, the series with the season and episode numbers.
+#[openapi(tag = "IMDB")]
+#[get("/imdb/title/<tconst>")]
+async fn title(
+    repository: &State<Repository>,
+    tconst: &str,
+) -> Result<Json<schemas::TitleDetails>, ApiError> {
+    Ok(Json(repository.title_details(tconst).await?))
+}
+
+/// The episodes of a series by season, with their ratings.
+/// Seasons and episodes without number come last, titles without episodes have no seasons.
+#[openapi(tag = "IMDB")]
+#[get("/imdb/title/<tconst>/episodes")]
+async fn episodes(
+    repository: &State<Repository>,
+    tconst: &str,
+) -> Result<Json<schemas::SeriesEpisodes>, ApiError> {
+    Ok(Json(repository.series_episodes(tconst).await?))
+}
+
+/// A contributor by id, with all their titles grouped by principal category (actor, director, writer, ...),
+/// the category with most titles first. Titles are ordered by year and have the job and characters of the contributor.
+/// The titletype is a comma-separated list of title types, e.g. movie,tvMovie. All title types by default.
+#[openapi(tag = "IMDB")]
+#[get("/imdb/principal/<nconst>?<titletype>")]
+async fn filmography(
+    repository: &State<Repository>,
+    nconst: &str,
+    titletype: Option<&str>,
+) -> Result<Json<schemas::Filmography>, ApiError> {
+    let title_types = titletype.map(title_types);
+    Ok(Json(
+        repository
+            .filmography(nconst, title_types.as_deref())
+            .await?,
+    ))
 }
 
 /// Search for contributors by name. You may use PostgreSQL wildcards.
+/// Without wildcards, names match ignoring case, accents and repeated spaces, e.g. penelope cruz.
 #[openapi(tag = "IMDB")]
 #[get("/imdb/principal?<name>&<use_wildcard>")]
 async fn contributor(
-    db: &DbPool,
+    repository: &State<Repository>,
     cache: &State<schemas::TitlePrincipalCache>,
     name: &str,
     use_wildcard: bool,
-) -> Result<Json<Vec<schemas::NameBasics>>, String> {
-    let result = repo::basics_for_name(&db.0, cache, name, use_wildcard).await;
-    match result {
-        Ok(names) => Ok(Json(names)),
-        Err(err) => Err(format!("{:?}", err)),
-    }
+) -> Result<Json<Vec<schemas::NameBasics>>, ApiError> {
+    let result = repository
+        .basics_for_name(cache, name, use_wildcard)
+        .await?;
+    Ok(Json(result))
 }
 
-fn busiest_actor(
-    cache: &State<schemas::TitlePrincipalCache>,
-    nconsts: Vec<String>,
-) -> Option<String> {
-    if nconsts.is_empty() {
-        None
-    } else {
-        let mut busiest: (String, usize) = (nconsts[0].clone(), 0);
-        for nconst in nconsts {
-            let score = cache.p_to_t(&nconst).map_or(0, |x| x.len());
-            if score > busiest.1 {
-                busiest = (nconst, score);
-            }
+/// The contributors sharing a name, and the one a search chose
+#[derive(Serialize, schemars::JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct Namesakes {
+    name: String,
+    /// the id of the contributor the search used, the one with most roles
+    chosen: String,
+    /// every contributor with the name, the busiest first
+    candidates: Vec<schemas::NameBasics>,
+}
+
+/// The id of the contributor a name stands for, with the namesakes if several contributors have the name.
+/// The busiest namesake is chosen, unless `strict` refuses ambiguous names.
+async fn resolve_name(
+    repository: &Repository,
+    cache: &schemas::TitlePrincipalCache,
+    name: &str,
+    strict: bool,
+) -> Result<(String, Option<Namesakes>), ApiError> {
+    let mut candidates = repository.basics_for_name(cache, name, false).await?;
+    match candidates.len() {
+        0 => Err(ApiError::not_found(
+            name,
+            format!("Could not find Contributor {}", name),
+        )),
+        1 => Ok((candidates.remove(0).get_name_id().to_string(), None)),
+        _ if strict => Err(ApiError::Ambiguous {
+            id: name.to_string(),
+            message: format!("{} contributors are named {}", candidates.len(), name),
+            candidates,
+        }),
+        _ => {
+            // the candidates are ordered by their roles, the busiest first
+            let chosen = candidates[0].get_name_id().to_string();
+            let namesakes = Namesakes {
+                name: name.to_string(),
+                chosen: chosen.clone(),
+                candidates,
+            };
+            Ok((chosen, Some(namesakes)))
         }
-        Some(busiest.0.to_string())
     }
 }
 
 /// Search the **shortest path between 2 actors or actresses**, identified by name.<br/>
 /// <ul>
-/// <li>In case two actors have the same name, the one with the most film references will be used.</li>
+/// <li>Names match ignoring case, accents and repeated spaces, e.g. tom hanks.</li>
+/// <li>In case two actors have the same name, the one with the most film references will be used,
+/// and the namesakes are listed with their birth year and known-for titles.
+/// The strict flag answers 409 with the namesakes instead.</li>
+/// <li>The search gives up after timeout_ms milliseconds or max_visits names, answering 504 with the degrees explored
+/// and the names visited. Both are capped by the server settings.</li>
+/// <li>Names further apart than max_degree are not searched, depth_limit_reached tells them from names
+/// without any path. It is capped by the server settings, 10 by default.</li>
 /// <li>The parallel flag enables a parallel (multi-CPU) search.</li>
-/// <li>Search is faster when the lesser-known actor is placed first.</li>
+/// <li>The search expands from both actors, the order of the names does not matter.</li>
+/// <li>The all_paths flag returns every shortest path, up to max_paths (at most 100), ordered by title and actor ids.</li>
+/// <li>Only titles matching the title type, start year, genre, votes and rating filters connect actors.</li>
+/// <li>The categories select the principals connecting through titles, e.g. director,actor for "worked with" chains.</li>
 /// </ul>
 #[openapi(tag = "IMDB")]
-#[get("/imdb/distance?<name1>&<name2>&<parallel>")]
+#[get(
+    "/imdb/distance?<name1>&<name2>&<parallel>&<all_paths>&<max_paths>&<strict>&<max_degree>&<timeout_ms>&<max_visits>&<filter..>"
+)]
+#[allow(clippy::too_many_arguments)]
 async fn name_distance(
-    db_pool: &DbPool,
+    repository: &State<Repository>,
     cache: &State<schemas::TitlePrincipalCache>,
+    search_config: &State<SearchConfig>,
     name1: &str,
     name2: &str,
     parallel: bool,
-) -> Result<Json<DistanceResult>, (Status, String)> {
-    if let Some(nconst1) = busiest_actor(cache, repo::nconst_for_name(&db_pool.0, name1).await?) {
-        if let Some(nconst2) = busiest_actor(cache, repo::nconst_for_name(&db_pool.0, name2).await?)
-        {
-            distance(db_pool, cache, &nconst1, &nconst2, parallel).await
-        } else {
-            Err((
-                Status::NotFound,
-                format!("Could not find Contributor {}", name2),
-            ))
-        }
-    } else {
-        Err((
-            Status::NotFound,
-            format!("Could not find Contributor {}", name1),
-        ))
-    }
+    all_paths: Option<bool>,
+    max_paths: Option<usize>,
+    strict: Option<bool>,
+    max_degree: Option<usize>,
+    timeout_ms: Option<u64>,
+    max_visits: Option<usize>,
+    filter: schemas::TitleFilterParams,
+) -> Result<Json<DistanceResult>, ApiError> {
+    let strict = strict.unwrap_or(false);
+    let (nconst1, namesakes1) = resolve_name(repository, cache, name1, strict).await?;
+    let (nconst2, namesakes2) = resolve_name(repository, cache, name2, strict).await?;
+    let Json(mut result) = distance(
+        repository,
+        cache,
+        search_config,
+        &nconst1,
+        &nconst2,
+        parallel,
+        all_paths,
+        max_paths,
+        max_degree,
+        timeout_ms,
+        max_visits,
+        filter,
+    )
+    .await?;
+    result.namesakes = namesakes1.into_iter().chain(namesakes2).collect();
+    Ok(Json(result))
 }
 
+/// Upper limit of the paths returned by a search for all shortest paths
+const MAX_PATHS: usize = 100;
+
 #[derive(Serialize, schemars::JsonSchema)]
 #[serde(crate = "rocket::serde")]
 struct DistanceResult {
     separation_degree: i32,
     response_time: Duration,
     connection_path: Vec<schemas::TitleToNames>,
+    /// every shortest path, if all_paths was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    connection_paths: Vec<Vec<schemas::TitleToNames>>,
+    /// no path was found within max_degree degrees, but the names may be connected beyond it.
+    /// Names without any path have a separation_degree of -1 and false here.
+    depth_limit_reached: bool,
+    /// the contributors sharing name1 or name2, if there are several
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    namesakes: Vec<Namesakes>,
+}
+
+/// The steps of a route of alternating names and titles.
+/// Steps shared by several routes are only retrieved once.
+async fn route_steps(
+    repository: &Repository,
+    route: &[String],
+    known_steps: &mut HashMap<(String, String, String), schemas::TitleToNames>,
+) -> Result<Vec<schemas::TitleToNames>, ApiError> {
+    let mut connection_path: Vec<schemas::TitleToNames> = Vec::new();
+    for idx in (0..route.len() - 1).step_by(2) {
+        let key = (
+            route[idx + 1].clone(),
+            route[idx].clone(),
+            route[idx + 2].clone(),
+        );
+        if !known_steps.contains_key(&key) {
+            let step = repository.title_to_names(&key.0, &key.1, &key.2).await?;
+            known_steps.insert(key.clone(), step);
+        }
+        connection_path.push(known_steps[&key].clone());
+    }
+    Ok(connection_path)
 }
 
-/// Search the shortest path between 2 actors, identified by their id
+/// Search the shortest path between 2 actors, identified by their id.
+/// The search gives up after timeout_ms milliseconds or max_visits names, answering 504 with the degrees explored
+/// and the names visited. Names further apart than max_degree are reported with depth_limit_reached.
 #[openapi(tag = "IMDB")]
-#[get("/imdb/distance/principal/<nconst1>?<nconst2>&<parallel>")]
+#[get(
+    "/imdb/distance/principal/<nconst1>?<nconst2>&<parallel>&<all_paths>&<max_paths>&<max_degree>&<timeout_ms>&<max_visits>&<filter..>"
+)]
+#[allow(clippy::too_many_arguments)]
 async fn distance(
-    db_pool: &DbPool,
+    repository: &State<Repository>,
     cache: &State<schemas::TitlePrincipalCache>,
+    search_config: &State<SearchConfig>,
     nconst1: &str,
     nconst2: &str,
     parallel: bool,
-) -> Result<Json<DistanceResult>, (Status, String)> {
+    all_paths: Option<bool>,
+    max_paths: Option<usize>,
+    max_degree: Option<usize>,
+    timeout_ms: Option<u64>,
+    max_visits: Option<usize>,
+    filter: schemas::TitleFilterParams,
+) -> Result<Json<DistanceResult>, ApiError> {
     let start_time = SystemTime::now();
-    let mut visited_titles: DashSet<String> = DashSet::with_capacity(100000);
-    let mut visited_names: DashSet<String> = DashSet::with_capacity(100000);
-    let first_level = vec![("".to_owned(), HashSet::from([nconst1.to_string()]))];
-    let result = kevinbacon::search_titles(
-        parallel,
-        cache,
-        &mut visited_titles,
-        &mut visited_names,
-        &first_level,
-        nconst2,
-        1,
-    );
+    let max_routes = if all_paths.unwrap_or(false) {
+        max_paths.unwrap_or(MAX_PATHS).clamp(1, MAX_PATHS)
+    } else {
+        1
+    };
+    let graph = cache.graph();
+    let filter = graph.title_filter(&filter)?;
+    // the search is CPU-bound, it runs on the blocking pool rather than on an async worker
+    let budget = Arc::new(search_config.budget(timeout_ms, max_visits));
+    let _cancel_on_drop = CancelOnDrop(budget.clone());
+    let max_degree = search_config.max_degree(max_degree);
+    let (name1, name2) = (nconst1.to_string(), nconst2.to_string());
+    let result = task::spawn_blocking(move || {
+        kevinbacon::search(
+            parallel, &graph, &filter, &name1, &name2, max_routes, max_degree, &budget,
+        )
+    })
+    .await
+    .expect("the separation search panicked");
 
     match result {
-        Ok(route) => {
+        Ok(kevinbacon::Routes {
+            routes,
+            depth_limit_reached,
+        }) => {
             let response_time = start_time.elapsed().unwrap();
             println!("Response time: {:?}", response_time);
 
-            if route.is_empty() {
+            if routes.is_empty() {
                 Ok(Json(DistanceResult {
                     separation_degree: -1,
                     response_time,
                     connection_path: vec![],
+                    connection_paths: vec![],
+                    depth_limit_reached,
+                    namesakes: vec![],
                 }))
             } else {
-                let separation_degree = (route.len() - 1) / 2;
-                let mut connection_path: Vec<schemas::TitleToNames> = Vec::new();
-                for i in 0..separation_degree {
-                    let idx: usize = (i * 2).try_into().unwrap();
-                    let step = repo::title_to_names(
-                        db_pool,
-                        &route[idx + 1],
-                        &route[idx],
-                        &route[idx + 2],
-                    )
-                    .await?;
-                    connection_path.push(step);
+                let separation_degree = (routes[0].len() - 1) / 2;
+                let mut known_steps = HashMap::new();
+                let mut connection_paths: Vec<Vec<schemas::TitleToNames>> = Vec::new();
+                for route in &routes {
+                    connection_paths.push(route_steps(repository, route, &mut known_steps).await?);
+                }
+                let connection_path = connection_paths[0].clone();
+                if !all_paths.unwrap_or(false) {
+                    connection_paths.clear();
                 }
 
                 Ok(Json(DistanceResult {
                     separation_degree: separation_degree.try_into().unwrap(),
                     response_time,
                     connection_path,
+                    connection_paths,
+                    depth_limit_reached,
+                    namesakes: vec![],
                 }))
             }
         }
-        Err(err) => Err((
-            Status::NotFound,
-            format!("Could not find actor with ID {}", err.0),
-        )),
+        Err(err) => Err(ApiError::from(err)),
+    }
+}
+
+/// Upper limit of the farthest names listed by a separation histogram
+const MAX_FARTHEST: usize = 20;
+
+/// The number of actors and actresses at each degree of separation from a contributor, identified by id,
+/// with the average degree and the farthest of them. Every connected name is visited, within the search limits
+/// of the server. The recently used histograms are kept until the graph is reloaded.
+#[openapi(tag = "IMDB")]
+#[get("/imdb/principal/<nconst>/separation-histogram")]
+async fn separation_histogram(
+    repository: &State<Repository>,
+    cache: &State<schemas::TitlePrincipalCache>,
+    search_config: &State<SearchConfig>,
+    nconst: &str,
+) -> Result<Json<schemas::SeparationHistogram>, ApiError> {
+    if let Some(histogram) = cache.histogram(nconst) {
+        return Ok(Json(histogram));
+    }
+
+    let graph = cache.graph();
+    let filter = graph.title_filter(&schemas::TitleFilterParams::default())?;
+    let budget = Arc::new(search_config.budget(None, None));
+    let _cancel_on_drop = CancelOnDrop(budget.clone());
+    let (search_graph, center) = (graph.clone(), nconst.to_string());
+    let reach = task::spawn_blocking(move || {
+        kevinbacon::reach(true, &search_graph, &filter, &center, MAX_FARTHEST, &budget)
+    })
+    .await
+    .expect("the separation histogram panicked")?;
+
+    let mut nconsts = reach.farthest.clone();
+    nconsts.push(nconst.to_string());
+    let names = repository.primary_names(&nconsts).await?;
+    let histogram =
+        schemas::SeparationHistogram::new(nconst, reach.counts, &reach.farthest, &names);
+    cache.set_histogram(&graph, histogram.clone());
+    Ok(Json(histogram))
+}
+
+/// Default and upper limit of the names of a network
+const DEFAULT_NETWORK_NAMES: usize = 100;
+const MAX_NETWORK_NAMES: usize = 1000;
+
+/// The actors and actresses within depth degrees of a contributor, identified by id, and the titles connecting them,
+/// as a list of nodes and edges for graph visualizations. Names and titles are nodes, edges go from names to titles.
+/// <ul>
+/// <li>The depth is 2 by default, and capped like the max_degree of searches.</li>
+/// <li>At most max_names names are returned, 100 by default and 1000 at most, the nearest first.
+/// truncated tells when names within the depth were left out.</li>
+/// <li>The search gives up like the distance searches do with their default limits, answering 504.</li>
+/// <li>The connecting titles can be restricted like in the distance searches, e.g. by titletype and startyear_from.</li>
+/// </ul>
+#[openapi(tag = "IMDB")]
+#[get("/imdb/principal/<nconst>/network?<depth>&<max_names>&<filter..>")]
+async fn network(
+    repository: &State<Repository>,
+    cache: &State<schemas::TitlePrincipalCache>,
+    search_config: &State<SearchConfig>,
+    nconst: &str,
+    depth: Option<usize>,
+    max_names: Option<usize>,
+    filter: schemas::TitleFilterParams,
+) -> Result<Json<schemas::Network>, ApiError> {
+    let depth = search_config.max_degree(Some(depth.unwrap_or(2)));
+    let max_names = max_names
+        .unwrap_or(DEFAULT_NETWORK_NAMES)
+        .clamp(1, MAX_NETWORK_NAMES);
+    let graph = cache.graph();
+    let filter = graph.title_filter(&filter)?;
+    let budget = Arc::new(search_config.budget(None, None));
+    let _cancel_on_drop = CancelOnDrop(budget.clone());
+    let center = nconst.to_string();
+    let network = task::spawn_blocking(move || {
+        kevinbacon::ego_network(&graph, &filter, &center, depth, max_names, &budget)
+    })
+    .await
+    .expect("the network search panicked")?;
+
+    let nconsts: Vec<String> = network
+        .names
+        .iter()
+        .map(|(nconst, _)| nconst.clone())
+        .collect();
+    let tconsts: Vec<String> = network
+        .titles
+        .iter()
+        .map(|(tconst, _)| tconst.clone())
+        .collect();
+    let names = repository.primary_names(&nconsts).await?;
+    let titles = repository.title_basics(&tconsts).await?;
+    Ok(Json(schemas::Network::new(
+        nconst, depth, network, &names, &titles,
+    )))
+}
+
+/// The chunks of a graph export, boxed to be named in the OpenAPI responses
+type ExportStream = ByteStream<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>>;
+
+/// Export the actor graph for Gephi, networkx or Graphviz, as a stream.
+/// <ul>
+/// <li>The format is graphml (default), gexf, dot, or csv for a list of edges without node attributes.</li>
+/// <li>The bipartite graph (default) has names and titles as nodes, and edges from names to their titles.
+/// The projected graph has names as nodes, and edges between names sharing titles, weighted by their number.</li>
+/// <li>Nodes have their kind, primary name or title, and the type and start year of titles.</li>
+/// <li>The titles and roles can be restricted like in the distance searches, actors and actresses by default.</li>
+/// <li>Exports of more nodes than the server allows, 500000 by default, are refused with 400.
+/// The export command of the server writes larger graphs.</li>
+/// </ul>
+#[openapi(tag = "IMDB")]
+#[get("/imdb/export?<format>&<kind>&<filter..>")]
+async fn graph_export(
+    repository: &State<Repository>,
+    cache: &State<schemas::TitlePrincipalCache>,
+    export_config: &State<ExportConfig>,
+    format: Option<export::ExportFormat>,
+    kind: Option<export::GraphKind>,
+    filter: schemas::TitleFilterParams,
+) -> Result<(ContentType, ExportStream), ApiError> {
+    let format = format.unwrap_or_default();
+    let graph = cache.graph();
+    let filter = graph.title_filter(&filter)?;
+    let export = export::GraphExport::new(graph, filter, format, kind.unwrap_or_default());
+    let (mut export, nodes) = task::spawn_blocking(move || {
+        let nodes = export.nodes();
+        (export, nodes)
+    })
+    .await
+    .expect("the graph export panicked");
+    if nodes.len() > export_config.export_max_nodes {
+        return Err(ApiError::bad_input(
+            None,
+            format!(
+                "The export has {} nodes, more than the {} allowed, restrict it with the filters",
+                nodes.len(),
+                export_config.export_max_nodes
+            ),
+        ));
+    }
+    // the attributes are retrieved before the response starts, so a database error is answered
+    // as such rather than ending the stream early with a truncated file
+    let attributes = if format == export::ExportFormat::Csv {
+        export::NodeAttributes::default()
+    } else {
+        export::NodeAttributes::fetch(repository, &nodes).await?
+    };
+    let attributes = Arc::new(attributes);
+
+    let chunks = rocket::async_stream::stream! {
+        loop {
+            let attributes = attributes.clone();
+            let (next, chunk) = task::spawn_blocking(move || {
+                let chunk = export.next_chunk(&attributes);
+                (export, chunk)
+            })
+            .await
+            .expect("the graph export panicked");
+            export = next;
+            match chunk {
+                Some(chunk) => yield chunk,
+                None => break,
+            }
+        }
+    };
+    Ok((format.content_type(), ByteStream(Box::pin(chunks))))
+}
+
+/// Load the cache from the snapshot file, if it matches the dataset fingerprint
+async fn load_snapshot(
+    cache: &schemas::TitlePrincipalCache,
+    snapshot: &Option<(PathBuf, String)>,
+) -> bool {
+    if let Some((path, fingerprint)) = snapshot.clone() {
+        let result = task::spawn_blocking(move || snapshot::load(&path, &fingerprint))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+        match result {
+            Ok(Some(graph)) => {
+                cache.set_graph(graph);
+                println!("Loaded the cache from graph snapshot");
+                return true;
+            }
+            Ok(None) => println!("Graph snapshot is missing or outdated"),
+            Err(err) => println!("Error reading graph snapshot: {}", err),
+        }
+    }
+    false
+}
+
+async fn save_snapshot(cache: &schemas::TitlePrincipalCache, snapshot: &Option<(PathBuf, String)>) {
+    if let Some((path, fingerprint)) = snapshot.clone() {
+        let graph = cache.graph();
+        let result = task::spawn_blocking(move || snapshot::save(&path, &fingerprint, &graph))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+        match result {
+            Ok(()) => println!("Saved graph snapshot"),
+            Err(err) => println!("Error writing graph snapshot: {}", err),
+        }
     }
 }
 
@@ -200,41 +736,169 @@ impl TitlePrincipalCacheLoader {
 impl Fairing for TitlePrincipalCacheLoader {
     fn info(&self) -> Info {
         Info {
-            name: "Retrieve Title to Actor mappings to a memory cache",
+            name: "Retrieve Title to Principal mappings to a memory cache",
             kind: Kind::Liftoff,
         }
     }
 
     async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
-        let state: Option<&State<schemas::TitlePrincipalCache>> = State::get(rocket);
+        load_cache(rocket).await;
+    }
+}
+
+/// Load the managed cache from the graph snapshot, or from the repository
+async fn load_cache<P: Phase>(rocket: &Rocket<P>) {
+    let state: Option<&State<schemas::TitlePrincipalCache>> = State::get(rocket);
 
-        if let Some(cache) = state {
-            let start_time = SystemTime::now();
-            // cache.insert("some".to_string(), "thing".to_string());
-            if let Some(db_pool) = DbPool::fetch(rocket) {
-                repo::titles_to_principals(db_pool, &cache).await;
+    if let Some(cache) = state {
+        let start_time = SystemTime::now();
+        if let Some(repository) = State::<Repository>::get(rocket) {
+            let path: Option<PathBuf> = rocket.figment().extract_inner("graph_snapshot").ok();
+            let mut categories: Vec<String> = rocket
+                .figment()
+                .extract_inner("principal_categories")
+                .unwrap_or_else(|_| {
+                    graph::DEFAULT_CATEGORIES
+                        .iter()
+                        .map(|category| category.to_string())
+                        .collect()
+                });
+            if categories.len() > graph::MAX_CATEGORIES {
+                println!(
+                    "Only the first {} principal categories are loaded",
+                    graph::MAX_CATEGORIES
+                );
+                categories.truncate(graph::MAX_CATEGORIES);
             }
+            // snapshots of other categories are outdated
+            let fingerprint = match repository.dataset_fingerprint().await {
+                Ok(fingerprint) => fingerprint.map(|f| format!("{}:{}", f, categories.join(","))),
+                Err(err) => {
+                    println!(
+                        "Error identifying the dataset, not using graph snapshots: {}",
+                        err
+                    );
+                    None
+                }
+            };
+            let snapshot = path.zip(fingerprint);
 
-            let (size_t, size_p) = cache.len();
-            println!(
-                "Inserted ({}, {}) rows to the cache in {:?} time",
-                size_t,
-                size_p,
-                start_time.elapsed().unwrap()
-            );
+            if !load_snapshot(cache, &snapshot).await {
+                repository.titles_to_principals(cache, &categories).await;
+                cache.finish();
+                save_snapshot(cache, &snapshot).await;
+            }
         }
+
+        let (size_t, size_p) = cache.len();
+        println!(
+            "Loaded {} titles and {} names to the cache in {:?} time",
+            size_t,
+            size_p,
+            start_time.elapsed().unwrap()
+        );
+    }
+}
+
+/// The `repository` setting, "postgres" by default
+fn repository_backend(rocket: &Rocket<Build>) -> String {
+    rocket
+        .figment()
+        .extract_inner("repository")
+        .unwrap_or_else(|_| "postgres".to_string())
+}
+
+/// Attach the repository selected by the `repository` setting: "postgres" (default), "sqlite"
+/// for the `imdb_sqlite` database, or "memory" to serve the TSV files of the `memory_dataset`
+/// directory without a database
+fn attach_repository(rocket: Rocket<Build>) -> Rocket<Build> {
+    let backend = repository_backend(&rocket);
+    match backend.as_str() {
+        "postgres" => rocket
+            .attach(DbPool::init())
+            .attach(migrate::SchemaMigrator::init())
+            .attach(AdHoc::try_on_ignite(
+                "PostgreSQL repository",
+                |rocket| async {
+                    match DbPool::fetch(&rocket) {
+                        Some(db_pool) => {
+                            let repository = PostgresRepository::new(db_pool.0.clone());
+                            Ok(rocket.manage(Arc::new(repository) as Repository))
+                        }
+                        None => Err(rocket),
+                    }
+                },
+            )),
+        "sqlite" => rocket
+            .attach(SqliteDbPool::init())
+            .attach(migrate::SchemaMigrator::init_sqlite())
+            .attach(AdHoc::try_on_ignite("SQLite repository", |rocket| async {
+                match SqliteDbPool::fetch(&rocket) {
+                    Some(db_pool) => {
+                        let repository = SqliteRepository::new(db_pool.0.clone());
+                        Ok(rocket.manage(Arc::new(repository) as Repository))
+                    }
+                    None => Err(rocket),
+                }
+            })),
+        "memory" => rocket.attach(AdHoc::try_on_ignite(
+            "In-memory repository",
+            |rocket| async {
+                let dir: PathBuf = rocket
+                    .figment()
+                    .extract_inner("memory_dataset")
+                    .unwrap_or_else(|_| PathBuf::from("fixtures"));
+                match task::spawn_blocking(move || MemoryRepository::load(&dir)).await {
+                    Ok(Ok(repository)) => Ok(rocket.manage(Arc::new(repository) as Repository)),
+                    Ok(Err(err)) => {
+                        println!("Error loading the in-memory repository: {}", err);
+                        Err(rocket)
+                    }
+                    Err(err) => {
+                        println!("Error loading the in-memory repository: {}", err);
+                        Err(rocket)
+                    }
+                }
+            },
+        )),
+        _ => rocket.attach(AdHoc::try_on_ignite(
+            "Unknown repository",
+            |rocket| async move {
+                println!(
+                    "Unknown repository {}, use postgres, sqlite or memory",
+                    backend
+                );
+                Err(rocket)
+            },
+        )),
     }
 }
 
-#[launch]
-fn rocket() -> _ {
-    rocket::build()
+fn rocket() -> Rocket<Build> {
+    build_rocket(rocket::build())
+}
+
+fn build_rocket(rocket: Rocket<Build>) -> Rocket<Build> {
+    attach_repository(rocket)
         .manage(schemas::TitlePrincipalCache::new())
-        .attach(DbPool::init())
+        .attach(AdHoc::config::<SearchConfig>())
+        .attach(AdHoc::config::<ExportConfig>())
         .attach(TitlePrincipalCacheLoader::init())
+        .register("/", catchers![error::not_found, error::unprocessable])
         .mount(
             "/",
-            openapi_get_routes![titles, contributor, name_distance, distance],
+            openapi_get_routes![
+                titles,
+                title,
+                episodes,
+                contributor,
+                filmography,
+                name_distance,
+                distance,
+                separation_histogram,
+                network,
+                graph_export
+            ],
         )
         .mount(
             "/swagger-ui/",
@@ -260,3 +924,463 @@ fn rocket() -> _ {
             }),
         )
 }
+
+/// Connect to the database of the configured repository and bring its schema up to date,
+/// without starting the server
+async fn migrate_db() -> Result<Rocket<Ignite>, String> {
+    let rocket = rocket::build();
+    let rocket = match repository_backend(&rocket).as_str() {
+        "sqlite" => rocket
+            .attach(SqliteDbPool::init())
+            .attach(migrate::SchemaMigrator::init_sqlite()),
+        "postgres" => rocket
+            .attach(DbPool::init())
+            .attach(migrate::SchemaMigrator::init()),
+        backend => return Err(format!("Repository {} has no database", backend)),
+    };
+    rocket.ignite().await.map_err(|err| format!("{}", err))
+}
+
+/// Load the IMDb datasets found in `dir` into the database of the configured repository
+async fn import(dir: &Path) -> Result<(), String> {
+    let rocket = migrate_db().await?;
+    let result = if let Some(db_pool) = State::<SqliteDbPool>::get(&rocket) {
+        import::import_dir_sqlite(db_pool, dir).await
+    } else {
+        let db_pool = DbPool::fetch(&rocket).ok_or("Database pool is not available")?;
+        import::import_dir(db_pool, dir).await
+    };
+    result.map_err(|err| format!("{}", err))
+}
+
+/// Write the actor graph of the configured repository to `path`
+async fn export_to_file(
+    path: &Path,
+    format: export::ExportFormat,
+    kind: export::GraphKind,
+) -> Result<(), String> {
+    let rocket = build_rocket(rocket::build())
+        .ignite()
+        .await
+        .map_err(|err| format!("{}", err))?;
+    load_cache(&rocket).await;
+    let repository = State::<Repository>::get(&rocket).ok_or("Repository is not available")?;
+    let cache =
+        State::<schemas::TitlePrincipalCache>::get(&rocket).ok_or("Cache is not available")?;
+
+    let graph = cache.graph();
+    let filter = graph
+        .title_filter(&schemas::TitleFilterParams::default())
+        .map_err(|err| format!("{}", err))?;
+    let mut export = export::GraphExport::new(graph, filter, format, kind);
+    let mut w = BufWriter::new(File::create(path).map_err(|err| format!("{}", err))?);
+    loop {
+        // the attributes are retrieved chunk by chunk, the whole graph may not fit in memory
+        let attributes = export::NodeAttributes::fetch(repository, &export.next_nodes())
+            .await
+            .map_err(|err| format!("{}", err))?;
+        match export.next_chunk(&attributes) {
+            Some(chunk) => w.write_all(&chunk).map_err(|err| format!("{}", err))?,
+            None => break,
+        }
+    }
+    w.flush().map_err(|err| format!("{}", err))?;
+    println!("Exported the graph to {}", path.display());
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let result = match args.get(1).map(|arg| arg.as_str()) {
+        Some("import") => match args.get(2) {
+            Some(dir) => import(Path::new(dir)).await,
+            None => Err("Usage: imdbcopy import <dir>".to_string()),
+        },
+        Some("export") => {
+            let usage =
+                "Usage: imdbcopy export <graphml|gexf|dot|csv> <file> [bipartite|projected]";
+            let format = args
+                .get(2)
+                .and_then(|format| export::ExportFormat::parse(format));
+            let kind = match args.get(4) {
+                Some(kind) => export::GraphKind::parse(kind),
+                None => Some(export::GraphKind::Bipartite),
+            };
+            match (format, args.get(3), kind) {
+                (Some(format), Some(file), Some(kind)) => {
+                    export_to_file(Path::new(file), format, kind).await
+                }
+                _ => Err(usage.to_string()),
+            }
+        }
+        Some("--migrate-only") => migrate_db().await.map(|_| ()),
+        Some(arg) => Err(format!("Unknown argument {}", arg)),
+        None => rocket()
+            .launch()
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("{}", err)),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
+
+    use crate::build_rocket;
+
+    /// A client of the API serving the fixtures dataset from memory
+    fn client() -> Client {
+        let figment = rocket::Config::figment()
+            .merge(("repository", "memory"))
+            .merge(("memory_dataset", "fixtures"))
+            .merge(("graph_snapshot", None::<String>));
+        Client::tracked(build_rocket(rocket::custom(figment))).unwrap()
+    }
+
+    #[test]
+    fn search_titles() {
+        let client = client();
+        let response = client
+            .get("/imdb/title?title_fragment=o&sort=rating&limit=2")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("X-Total-Count"), Some("5"));
+        let titles: Value = response.into_json().unwrap();
+        assert_eq!(titles[0]["primarytitle"], "Forrest Gump");
+        assert_eq!(titles[1]["primarytitle"], "Apollo 13");
+        assert_eq!(titles[1]["principals"][0]["nconst"], "nm0000158");
+
+        let response = client.get("/imdb/title?title_fragment=Nothing").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn search_titles_fuzzy() {
+        let client = client();
+        let response = client
+            .get("/imdb/title?title_fragment=forest%20gump&mode=fuzzy")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let titles: Value = response.into_json().unwrap();
+        assert_eq!(titles[0]["primarytitle"], "Forrest Gump");
+        assert_eq!(titles[0]["relevance"], 0.7857);
+
+        let response = client
+            .get("/imdb/title?title_fragment=Gump&sort=relevance")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn search_alternate_titles() {
+        let client = client();
+        let response = client.get("/imdb/title?title_fragment=fratz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let titles: Value = response.into_json().unwrap();
+        assert_eq!(titles[0]["primarytitle"], "Funny Face");
+        assert_eq!(titles[0]["aka"]["title"], "Ein süßer Fratz");
+        assert_eq!(titles[0]["aka"]["region"], "DE");
+
+        let response = client
+            .get("/imdb/title?title_fragment=fratz&region=fr")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .get("/imdb/title?title_fragment=forrest&region=fr")
+            .dispatch();
+        let titles: Value = response.into_json().unwrap();
+        assert!(titles[0].get("aka").is_none());
+    }
+
+    #[test]
+    fn title_details() {
+        let client = client();
+        let response = client.get("/imdb/title/tt0109830").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let title: Value = response.into_json().unwrap();
+        assert_eq!(title["primarytitle"], "Forrest Gump");
+        assert_eq!(title["numvotes"], 2200000);
+        assert_eq!(title["principals"][1]["primaryname"], "Robin Wright");
+        assert_eq!(
+            title["crew"]["directors"][0]["primaryname"],
+            "Robert Zemeckis"
+        );
+        assert_eq!(title["crew"]["writers"][0]["nconst"], "nm0744839");
+        assert_eq!(title["akas"][1]["region"], "FR");
+        assert!(title.get("episode").is_none());
+
+        let response = client.get("/imdb/title/tt0583459").dispatch();
+        let episode: Value = response.into_json().unwrap();
+        assert_eq!(episode["episode"]["series"]["primarytitle"], "Friends");
+        assert_eq!(episode["episode"]["seasonnumber"], 1);
+        assert_eq!(episode["episode"]["episodenumber"], 9);
+        assert_eq!(episode["akas"].as_array().unwrap().len(), 0);
+
+        let response = client.get("/imdb/title/tt9999999").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn series_episodes() {
+        let client = client();
+        let response = client
+            .get("/imdb/title?title_fragment=friends&titletype=tvSeries,tvMiniSeries")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let titles: Value = response.into_json().unwrap();
+        assert_eq!(titles[0]["tconst"], "tt0108778");
+
+        let response = client.get("/imdb/title/tt0108778/episodes").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let series: Value = response.into_json().unwrap();
+        assert_eq!(series["series"]["primarytitle"], "Friends");
+        let seasons = series["seasons"].as_array().unwrap();
+        assert_eq!(seasons.len(), 2);
+        assert_eq!(seasons[0]["seasonnumber"], 1);
+        assert_eq!(seasons[0]["episodes"][0]["tconst"], "tt0583434");
+        assert_eq!(seasons[0]["episodes"][1]["averagerating"], 8.1);
+        assert_eq!(seasons[1]["episodes"][0]["episodenumber"], 12);
+
+        let response = client.get("/imdb/title/tt9999999/episodes").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn search_contributors() {
+        let client = client();
+        let response = client
+            .get("/imdb/principal?name=Kevin%25&use_wildcard=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let names: Value = response.into_json().unwrap();
+        assert_eq!(names[0]["primaryname"], "Kevin Bacon");
+        assert_eq!(names[0]["actorroles"], 3);
+        assert_eq!(names[0]["knownfortitles"][1]["primarytitle"], "Apollo 13");
+
+        let response = client
+            .get("/imdb/principal?name=penelope%20cruz")
+            .dispatch();
+        let names: Value = response.into_json().unwrap();
+        assert_eq!(names[0]["primaryname"], "Penélope Cruz");
+    }
+
+    #[test]
+    fn contributor_filmography() {
+        let client = client();
+        let response = client.get("/imdb/principal/nm0000102").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let filmography: Value = response.into_json().unwrap();
+        assert_eq!(filmography["primaryname"], "Kevin Bacon");
+        let titles = &filmography["categories"][0]["titles"];
+        assert_eq!(filmography["categories"][0]["category"], "actor");
+        assert_eq!(titles.as_array().unwrap().len(), 3);
+        assert_eq!(titles[0]["primarytitle"], "Footloose");
+        assert_eq!(titles[1]["characters"], r#"["Jack Swigert"]"#);
+        assert_eq!(titles[2]["startyear"], 2000);
+
+        let response = client
+            .get("/imdb/principal/nm0000165?titletype=tvSeries")
+            .dispatch();
+        let filmography: Value = response.into_json().unwrap();
+        assert!(filmography["categories"].as_array().unwrap().is_empty());
+
+        let response = client.get("/imdb/principal/nm9999999").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn separation_histogram() {
+        let client = client();
+        let response = client
+            .get("/imdb/principal/nm0000102/separation-histogram")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let histogram: Value = response.into_json().unwrap();
+        assert_eq!(histogram["center"]["primaryname"], "Kevin Bacon");
+        assert_eq!(histogram["counts"], json!([1, 2, 1]));
+        assert_eq!(histogram["reachable"], 3);
+        assert_eq!(histogram["max_degree"], 2);
+        assert_eq!(histogram["farthest"][0]["primaryname"], "Robin Wright");
+
+        // served from the cache the second time
+        let response = client
+            .get("/imdb/principal/nm0000102/separation-histogram")
+            .dispatch();
+        assert_eq!(response.into_json::<Value>().unwrap(), histogram);
+
+        let response = client
+            .get("/imdb/principal/nm9999999/separation-histogram")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn contributor_network() {
+        let client = client();
+        let response = client
+            .get("/imdb/principal/nm0000102/network?depth=1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let network: Value = response.into_json().unwrap();
+        let nodes = network["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes[0]["label"], "Kevin Bacon");
+        assert_eq!(nodes[0]["degree"], 0);
+        assert_eq!(nodes[3]["kind"], "title");
+        assert_eq!(nodes[3]["label"], "Apollo 13");
+        assert_eq!(network["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(network["edges"][0]["target"], "tt0112384");
+        assert_eq!(network["truncated"], false);
+
+        let response = client
+            .get("/imdb/principal/nm0000102/network?max_names=2")
+            .dispatch();
+        let network: Value = response.into_json().unwrap();
+        assert_eq!(network["depth"], 2);
+        assert_eq!(network["truncated"], true);
+
+        let response = client
+            .get("/imdb/principal/nm0000102/network?titletype=movie&startyear_from=2000")
+            .dispatch();
+        let network: Value = response.into_json().unwrap();
+        assert_eq!(network["nodes"].as_array().unwrap().len(), 1);
+        assert!(network["edges"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn graph_export() {
+        let client = client();
+        let response = client.get("/imdb/export").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type().unwrap().to_string(),
+            "application/graphml+xml"
+        );
+        let graphml = response.into_string().unwrap();
+        assert!(graphml.contains(
+            "<node id=\"nm0000102\"><data key=\"kind\">name</data><data key=\"label\">Kevin Bacon</data></node>"
+        ));
+        assert!(graphml
+            .contains("<data key=\"label\">Apollo 13</data><data key=\"titletype\">movie</data>"));
+        assert!(graphml.contains("<edge source=\"nm0000158\" target=\"tt0112384\"/>"));
+        assert!(graphml.ends_with("</graph>\n</graphml>\n"));
+
+        let response = client
+            .get("/imdb/export?format=gexf&kind=projected")
+            .dispatch();
+        let gexf = response.into_string().unwrap();
+        assert!(gexf.contains("<node id=\"nm0000705\" label=\"Robin Wright\">"));
+        assert!(!gexf.contains("<node id=\"tt"));
+        assert!(gexf
+            .contains("<edge id=\"4\" source=\"nm0000158\" target=\"nm0000705\" weight=\"1\"/>"));
+
+        let response = client
+            .get("/imdb/export?format=dot&startyear_from=1990")
+            .dispatch();
+        let dot = response.into_string().unwrap();
+        assert!(dot.starts_with("graph imdb {\n"));
+        assert!(dot.contains("\"nm0000102\" -- \"tt0112384\";"));
+        assert!(!dot.contains("tt0087277"));
+
+        let response = client
+            .get("/imdb/export?format=csv&kind=projected")
+            .dispatch();
+        assert_eq!(
+            response.into_string().unwrap(),
+            "source,target,weight\nnm0000001,nm0000031,1\nnm0000102,nm0000158,1\nnm0000102,nm0000200,1\n\
+             nm0000158,nm0000200,1\nnm0000158,nm0000705,1\n"
+        );
+
+        let figment = rocket::Config::figment()
+            .merge(("repository", "memory"))
+            .merge(("memory_dataset", "fixtures"))
+            .merge(("graph_snapshot", None::<String>))
+            .merge(("export_max_nodes", 5));
+        let client = Client::tracked(build_rocket(rocket::custom(figment))).unwrap();
+        let response = client.get("/imdb/export?kind=projected").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .get("/imdb/export?kind=projected&startyear_from=1995")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn distances() {
+        let client = client();
+        let response = client
+            .get("/imdb/distance?name1=Robin%20Wright&name2=Kevin%20Bacon")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let result: Value = response.into_json().unwrap();
+        assert_eq!(result["separation_degree"], 2);
+        assert_eq!(result["connection_path"][0]["primarytitle"], "Forrest Gump");
+        assert_eq!(result["connection_path"][1]["primarytitle"], "Apollo 13");
+        // the actress was chosen over her namesake without roles
+        let namesakes = &result["namesakes"][0];
+        assert_eq!(namesakes["name"], "Robin Wright");
+        assert_eq!(namesakes["chosen"], "nm0000705");
+        assert_eq!(namesakes["candidates"][1]["birthyear"], 1971);
+        assert_eq!(
+            namesakes["candidates"][1]["knownfortitles"][0]["primarytitle"],
+            "Hollow Man"
+        );
+
+        let response = client
+            .get("/imdb/distance?name1=robin%20%20wright&name2=KEVIN%20BACON&strict=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        let error: Value = response.into_json().unwrap();
+        assert_eq!(error["code"], "ambiguous");
+        assert_eq!(error["candidates"].as_array().unwrap().len(), 2);
+
+        let response = client
+            .get("/imdb/distance?name1=Tom%20Hanks&name2=kevin%20bacon&strict=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let result: Value = response.into_json().unwrap();
+        assert_eq!(result["separation_degree"], 1);
+        assert!(result.get("namesakes").is_none());
+
+        let response = client
+            .get("/imdb/distance/principal/nm0000001?nconst2=nm0000102")
+            .dispatch();
+        let result: Value = response.into_json().unwrap();
+        assert_eq!(result["separation_degree"], -1);
+        assert_eq!(result["depth_limit_reached"], false);
+
+        let response = client
+            .get("/imdb/distance?name1=Robin%20Wright&name2=Kevin%20Bacon&max_degree=1")
+            .dispatch();
+        let result: Value = response.into_json().unwrap();
+        assert_eq!(result["separation_degree"], -1);
+        assert_eq!(result["depth_limit_reached"], true);
+
+        let response = client
+            .get("/imdb/distance/principal/nm9999999?nconst2=nm0000102")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let error: Value = response.into_json().unwrap();
+        assert_eq!(error["code"], "not_found");
+        assert_eq!(error["id"], "nm9999999");
+
+        let response = client
+            .get("/imdb/distance/principal/nm0000705?nconst2=nm0000102&max_visits=1")
+            .dispatch();
+        assert_eq!(response.status(), Status::GatewayTimeout);
+        let error: Value = response.into_json().unwrap();
+        assert_eq!(error["code"], "timeout");
+        assert_eq!(error["stats"]["levels"], 1);
+        assert!(error["stats"]["names_visited"].as_u64().unwrap() >= 1);
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::error::ApiError;
+use crate::import::{dataset_path, ColumnKind, Dataset, ImportError, TsvReader, DATASETS};
+use crate::repo::{with_known_titles, MovieRepository};
+use crate::schemas::{
+    DbRow, Episode, EpisodeSeries, Filmography, FilmographyTitle, NameBasics, SeriesEpisodes,
+    TitleAka, TitleBasics, TitleCrew, TitleDetails, TitlePrincipal, TitlePrincipalCache,
+    TitleQuery, TitleToNames,
+};
+use crate::similarity;
+
+/// A row of a dataset or of a join, by lower-case column name. Missing columns are NULL.
+#[derive(Clone, Debug, Default)]
+struct MemoryRow(HashMap<String, String>);
+
+impl MemoryRow {
+    fn get(&self, column: &str) -> Option<&str> {
+        self.0.get(column).map(|v| v.as_str())
+    }
+
+    /// Add the columns of `other`, renamed with `suffix`
+    fn join(mut self, other: &MemoryRow, columns: &[&str], suffix: &str) -> MemoryRow {
+        for column in columns {
+            if let Some(value) = other.get(column) {
+                self.0
+                    .insert(format!("{}{}", column, suffix), value.to_string());
+            }
+        }
+        self
+    }
+}
+
+impl DbRow for MemoryRow {
+    fn string(&self, column: &str) -> String {
+        self.get(column).unwrap_or_default().to_string()
+    }
+    fn i32(&self, column: &str) -> i32 {
+        self.opt_i32(column).unwrap_or_default()
+    }
+    fn bool(&self, column: &str) -> bool {
+        self.get(column) == Some("true")
+    }
+    fn opt_string(&self, column: &str) -> Option<String> {
+        self.get(column).map(|v| v.to_string())
+    }
+    fn opt_i32(&self, column: &str) -> Option<i32> {
+        self.get(column).and_then(|v| v.parse().ok())
+    }
+    fn opt_f64(&self, column: &str) -> Option<f64> {
+        self.get(column).and_then(|v| v.parse().ok())
+    }
+}
+
+/// Serves the IMDb datasets from memory, for tests and demos without a database.
+/// The TSV files are read at startup, like the `import` subcommand reads them.
+pub struct MemoryRepository {
+    /// title_basics rows joined with their title_ratings
+    titles: BTreeMap<String, MemoryRow>,
+    names: BTreeMap<String, MemoryRow>,
+    /// name ids by `similarity::search_name` of their name
+    searchnames: HashMap<String, Vec<String>>,
+    /// title_principals rows by title, in billing order
+    principals: BTreeMap<String, Vec<MemoryRow>>,
+    /// alternate titles by title, in title.akas order
+    akas: BTreeMap<String, Vec<TitleAka>>,
+    /// title_episode rows by series
+    episodes: BTreeMap<String, Vec<MemoryRow>>,
+    /// title_episode rows by episode
+    series: BTreeMap<String, MemoryRow>,
+    /// title_crew rows by title
+    crew: BTreeMap<String, MemoryRow>,
+}
+
+fn read_dataset(dir: &Path, dataset: &Dataset) -> Result<Vec<MemoryRow>, ImportError> {
+    let path = match dataset_path(dir, dataset) {
+        Some(path) => path,
+        None => {
+            println!(
+                "Dataset {} not found in {}, skipping",
+                dataset.file,
+                dir.display()
+            );
+            return Ok(vec![]);
+        }
+    };
+    let reader = TsvReader::open(&path).map_err(|err| ImportError::Io(path.clone(), err))?;
+    let mut indexes: Vec<(String, usize, ColumnKind)> = Vec::new();
+    for (column, kind) in dataset.columns {
+        match reader.column_index(column) {
+            Some(idx) => indexes.push((column.to_lowercase(), idx, *kind)),
+            None => {
+                return Err(ImportError::Format(
+                    path,
+                    format!("missing column {}", column),
+                ))
+            }
+        }
+    }
+
+    let mut rows: Vec<MemoryRow> = Vec::new();
+    for record in reader {
+        let record = record.map_err(|err| ImportError::Io(path.clone(), err))?;
+        let mut row = MemoryRow::default();
+        for (column, idx, kind) in &indexes {
+            let value = match (kind, record.get(*idx)) {
+                (_, None) => continue,
+                (ColumnKind::Bool, Some(value)) => (value == "1").to_string(),
+                (ColumnKind::Array, Some(_)) => record.array(*idx).join(","),
+                (_, Some(value)) => value.to_string(),
+            };
+            row.0.insert(column.clone(), value);
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// A SQL `LIKE` match, `%` matching any text and `_` any character
+fn like(pattern: &[char], value: &[char]) -> bool {
+    // on a mismatch, retry from the last % matching one more character, instead of backtracking
+    let (mut p, mut v) = (0, 0);
+    let mut last_wildcard: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            last_wildcard = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '_' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if let Some((wildcard, matched)) = last_wildcard {
+            last_wildcard = Some((wildcard, matched + 1));
+            p = wildcard + 1;
+            v = matched + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+impl MemoryRepository {
+    /// Read the IMDb TSV files found in `dir`, plain or gzip-compressed
+    pub fn load(dir: &Path) -> Result<MemoryRepository, ImportError> {
+        let mut tables: HashMap<&str, Vec<MemoryRow>> = HashMap::new();
+        for dataset in DATASETS {
+            tables.insert(dataset.table, read_dataset(dir, dataset)?);
+        }
+        let mut take = |table: &str| tables.remove(table).unwrap_or_default();
+
+        let ratings: HashMap<String, MemoryRow> = take("title_ratings")
+            .into_iter()
+            .map(|row| (row.string("tconst"), row))
+            .collect();
+        let titles = take("title_basics")
+            .into_iter()
+            .map(|row| {
+                let tconst = row.string("tconst");
+                let row = match ratings.get(&tconst) {
+                    Some(rating) => row.join(rating, &["averagerating", "numvotes"], ""),
+                    None => row,
+                };
+                (tconst, row)
+            })
+            .collect();
+        let names: BTreeMap<String, MemoryRow> = take("name_basics")
+            .into_iter()
+            .map(|row| (row.string("nconst"), row))
+            .collect();
+        let mut searchnames: HashMap<String, Vec<String>> = HashMap::new();
+        for (nconst, row) in &names {
+            if let Some(primaryname) = row.get("primaryname") {
+                searchnames
+                    .entry(similarity::search_name(primaryname))
+                    .or_default()
+                    .push(nconst.clone());
+            }
+        }
+        let mut principals: BTreeMap<String, Vec<MemoryRow>> = BTreeMap::new();
+        for row in take("title_principals") {
+            principals
+                .entry(row.string("tconst"))
+                .or_default()
+                .push(row);
+        }
+        for rows in principals.values_mut() {
+            rows.sort_by_key(|row| row.i32("ordering"));
+        }
+        let mut aka_rows = take("title_akas");
+        aka_rows.sort_by_key(|row| row.i32("ordering"));
+        let mut akas: BTreeMap<String, Vec<TitleAka>> = BTreeMap::new();
+        for row in aka_rows {
+            akas.entry(row.string("titleid"))
+                .or_default()
+                .push(TitleAka::from_db_row(&row));
+        }
+
+        let mut episodes: BTreeMap<String, Vec<MemoryRow>> = BTreeMap::new();
+        let mut series: BTreeMap<String, MemoryRow> = BTreeMap::new();
+        for row in take("title_episode") {
+            series.insert(row.string("tconst"), row.clone());
+            episodes
+                .entry(row.string("parenttconst"))
+                .or_default()
+                .push(row);
+        }
+        let crew = take("title_crew")
+            .into_iter()
+            .map(|row| (row.string("tconst"), row))
+            .collect();
+
+        Ok(MemoryRepository {
+            titles,
+            names,
+            searchnames,
+            principals,
+            akas,
+            episodes,
+            series,
+            crew,
+        })
+    }
+
+    fn principals_of(&self, tconst: &str) -> &[MemoryRow] {
+        self.principals
+            .get(tconst)
+            .map_or(&[], |rows| rows.as_slice())
+    }
+
+    /// A principal row joined with its name, `None` for unknown names
+    fn with_name(&self, principal: &MemoryRow) -> Option<MemoryRow> {
+        let name = self.names.get(principal.get("nconst")?)?;
+        Some(
+            principal
+                .clone()
+                .join(name, &["primaryname", "birthyear", "deathyear"], ""),
+        )
+    }
+
+    /// The principals of a title in billing order, with their names
+    fn title_principals(&self, tconst: &str) -> Vec<TitlePrincipal> {
+        self.principals_of(tconst)
+            .iter()
+            .filter_map(|principal| self.with_name(principal))
+            .map(|principal| TitlePrincipal::from_db_row(&principal))
+            .collect()
+    }
+
+    /// The ids of the names matching `name` ignoring case, accents and repeated whitespace
+    fn nconsts_named(&self, name: &str) -> &[String] {
+        self.searchnames
+            .get(&similarity::search_name(name))
+            .map_or(&[], |nconsts| nconsts.as_slice())
+    }
+
+    /// The principal row of a name in a title, joined with the name
+    fn principal(&self, tconst: &str, nconst: &str) -> Option<MemoryRow> {
+        self.principals_of(tconst)
+            .iter()
+            .find(|row| row.get("nconst") == Some(nconst))
+            .and_then(|row| self.with_name(row))
+    }
+}
+
+#[rocket::async_trait]
+impl MovieRepository for MemoryRepository {
+    async fn titles_by_name(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+        let mut titles: Vec<TitleDetails> = self
+            .titles
+            .values()
+            .filter(|row| {
+                row.get("numvotes").is_some()
+                    && row
+                        .get("titletype")
+                        .is_some_and(|titletype| query.title_types.iter().any(|t| t == titletype))
+            })
+            .filter_map(|row| {
+                let tconst = row.get("tconst")?;
+                let akas: Vec<TitleAka> = self
+                    .akas
+                    .get(tconst)
+                    .into_iter()
+                    .flatten()
+                    .filter(|aka| aka.is_for(query.region.as_deref(), query.language.as_deref()))
+                    .cloned()
+                    .collect();
+                TitleDetails::from_db_row(row).matching(query, &akas)
+            })
+            .collect();
+        if titles.is_empty() {
+            return Err(ApiError::not_found(
+                &query.fragment,
+                format!("Could not find Title {}", query.fragment),
+            ));
+        }
+        titles.sort_by(|a, b| query.sort.compare(a, b));
+
+        let total = titles.len() as i64;
+        let page = titles
+            .into_iter()
+            .skip(query.offset as usize)
+            .take(query.limit as usize)
+            .map(|mut title| {
+                title.add_principals(&self.title_principals(title.get_title_id()));
+                title
+            })
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn title_details(&self, tconst: &str) -> Result<TitleDetails, ApiError> {
+        let mut title = self
+            .titles
+            .get(tconst)
+            .map(|row| TitleDetails::from_db_row(row))
+            .ok_or_else(|| {
+                ApiError::not_found(tconst, format!("Could not find Title {}", tconst))
+            })?;
+        title.add_principals(&self.title_principals(tconst));
+
+        let mut crew = self
+            .crew
+            .get(tconst)
+            .map(|row| TitleCrew::from_db_row(row))
+            .unwrap_or_default();
+        crew.set_names(&self.primary_names(&crew.name_ids()).await?);
+
+        let akas = self.akas.get(tconst).cloned().unwrap_or_default();
+        let episode = self.series.get(tconst).map(|row| {
+            let parenttconst = row.string("parenttconst");
+            let series = match self.titles.get(&parenttconst) {
+                Some(series) => series.clone(),
+                None => MemoryRow(HashMap::from([("tconst".to_string(), parenttconst)])),
+            };
+            EpisodeSeries::from_db_row(&series.join(row, &["seasonnumber", "episodenumber"], ""))
+        });
+
+        title.add_details(crew, akas, episode);
+        Ok(title)
+    }
+
+    async fn basics_for_name(
+        &self,
+        cache: &TitlePrincipalCache,
+        name: &str,
+        use_wildcard: bool,
+    ) -> Result<Vec<NameBasics>, ApiError> {
+        let names: Vec<NameBasics> = if use_wildcard {
+            let pattern: Vec<char> = name.chars().collect();
+            self.names
+                .values()
+                .filter(|row| {
+                    row.get("primaryname").is_some_and(|primaryname| {
+                        like(&pattern, &primaryname.chars().collect::<Vec<char>>())
+                    })
+                })
+                .map(|row| NameBasics::from_db_row(row))
+                .collect()
+        } else {
+            self.nconsts_named(name)
+                .iter()
+                .filter_map(|nconst| self.names.get(nconst))
+                .map(|row| NameBasics::from_db_row(row))
+                .collect()
+        };
+
+        let tconsts: Vec<String> = names.iter().flat_map(|n| n.title_ids()).collect();
+        let known_titles = self.title_basics(&tconsts).await?;
+        Ok(with_known_titles(cache, names, &known_titles))
+    }
+
+    async fn primary_names(&self, nconsts: &[String]) -> Result<HashMap<String, String>, ApiError> {
+        Ok(nconsts
+            .iter()
+            .filter_map(|nconst| {
+                let primaryname = self.names.get(nconst)?.opt_string("primaryname")?;
+                Some((nconst.clone(), primaryname))
+            })
+            .collect())
+    }
+
+    async fn title_basics(
+        &self,
+        tconsts: &[String],
+    ) -> Result<HashMap<String, TitleBasics>, ApiError> {
+        Ok(tconsts
+            .iter()
+            .filter_map(|tconst| {
+                let row = self.titles.get(tconst)?;
+                Some((tconst.clone(), TitleBasics::from_db_row(row)))
+            })
+            .collect())
+    }
+
+    async fn filmography(
+        &self,
+        nconst: &str,
+        title_types: Option<&[String]>,
+    ) -> Result<Filmography, ApiError> {
+        let name = self.names.get(nconst).ok_or_else(|| {
+            ApiError::not_found(nconst, format!("Could not find Name {}", nconst))
+        })?;
+        let mut titles: Vec<(String, FilmographyTitle)> = Vec::new();
+        for (tconst, principals) in &self.principals {
+            let title = match self.titles.get(tconst) {
+                Some(title) => title,
+                None => continue,
+            };
+            if let Some(title_types) = title_types {
+                if !title
+                    .get("titletype")
+                    .is_some_and(|titletype| title_types.iter().any(|t| t == titletype))
+                {
+                    continue;
+                }
+            }
+            for principal in principals {
+                if principal.get("nconst") == Some(nconst) {
+                    let row = title
+                        .clone()
+                        .join(principal, &["category", "job", "characters"], "");
+                    titles.push((row.string("category"), FilmographyTitle::from_db_row(&row)));
+                }
+            }
+        }
+        Ok(Filmography::new(name, titles))
+    }
+
+    async fn title_to_names(
+        &self,
+        tconst: &str,
+        nconst1: &str,
+        nconst2: &str,
+    ) -> Result<TitleToNames, ApiError> {
+        let columns = ["nconst", "characters", "primaryname"];
+        match (
+            self.titles.get(tconst),
+            self.principal(tconst, nconst1),
+            self.principal(tconst, nconst2),
+        ) {
+            (Some(title), Some(principal1), Some(principal2)) => {
+                let row =
+                    title
+                        .clone()
+                        .join(&principal1, &columns, "1")
+                        .join(&principal2, &columns, "2");
+                Ok(TitleToNames::from_db_row(&row))
+            }
+            _ => Err(ApiError::not_found(
+                tconst,
+                format!(
+                    "Could not find title {} with principals {}, {}",
+                    tconst, nconst1, nconst2
+                ),
+            )),
+        }
+    }
+
+    async fn series_episodes(&self, tconst: &str) -> Result<SeriesEpisodes, ApiError> {
+        let series = self
+            .titles
+            .get(tconst)
+            .map(|row| TitleBasics::from_db_row(row))
+            .ok_or_else(|| {
+                ApiError::not_found(tconst, format!("Could not find Title {}", tconst))
+            })?;
+        let episodes = self
+            .episodes
+            .get(tconst)
+            .into_iter()
+            .flatten()
+            .map(|row| {
+                let row = match self.titles.get(&row.string("tconst")) {
+                    Some(title) => row.clone().join(
+                        title,
+                        &["primarytitle", "startyear", "averagerating", "numvotes"],
+                        "",
+                    ),
+                    None => row.clone(),
+                };
+                (row.opt_i32("seasonnumber"), Episode::from_db_row(&row))
+            })
+            .collect();
+        Ok(SeriesEpisodes::new(series, episodes))
+    }
+
+    async fn dataset_fingerprint(&self) -> Result<Option<String>, ApiError> {
+        Ok(None)
+    }
+
+    async fn titles_to_principals(&self, cache: &TitlePrincipalCache, categories: &[String]) {
+        cache.insert_categories(categories);
+        let mut tconsts: BTreeSet<&str> = BTreeSet::new();
+        for (tconst, principals) in &self.principals {
+            for principal in principals {
+                if let (Some(nconst), Some(category)) =
+                    (principal.get("nconst"), principal.get("category"))
+                {
+                    if categories.iter().any(|c| c == category) {
+                        cache.insert(tconst, nconst, category);
+                        tconsts.insert(tconst);
+                    }
+                }
+            }
+        }
+        for tconst in tconsts {
+            if let Some(title) = self.titles.get(tconst) {
+                cache.insert_title(&TitleDetails::from_db_row(title));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::like;
+
+    #[test]
+    fn like_wildcards() {
+        let chars = |s: &str| s.chars().collect::<Vec<char>>();
+        assert!(like(&chars("Kevin%"), &chars("Kevin Bacon")));
+        assert!(like(&chars("%Bac_n"), &chars("Kevin Bacon")));
+        assert!(like(&chars("%"), &chars("")));
+        assert!(!like(&chars("Kevin"), &chars("Kevin Bacon")));
+        assert!(!like(&chars("_Kevin%"), &chars("Kevin Bacon")));
+        assert!(like(&chars("%e%i%_a%n"), &chars("Kevin Bacon")));
+        assert!(like(&chars("K%%n%B%"), &chars("Kevin Bacon")));
+        assert!(!like(&chars("%n%n%n%"), &chars("Kevin Bacon")));
+        let many = chars(&"%a".repeat(30));
+        assert!(!like(&many, &chars(&"a".repeat(29))));
+        assert!(like(&many, &chars(&"a".repeat(40))));
+    }
+}
@@ -0,0 +1,257 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Rocket};
+use rocket_db_pools::sqlx::{self, Executor};
+use rocket_db_pools::Database;
+
+use crate::schemas::DbRow;
+use crate::similarity;
+use crate::{DbPool, SqliteDbPool};
+
+pub struct Migration {
+    version: i32,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// The schema migrations in the order they are applied. Never edit an applied migration, add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create imdb tables",
+        sql: include_str!("../migrations/0001_create_imdb_tables.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "create query indexes",
+        sql: include_str!("../migrations/0002_create_query_indexes.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "create dataset imports",
+        sql: include_str!("../migrations/0003_create_dataset_imports.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "create title search indexes",
+        sql: include_str!("../migrations/0004_create_title_search_indexes.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "create title akas",
+        sql: include_str!("../migrations/0005_create_title_akas.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "create title episode",
+        sql: include_str!("../migrations/0006_create_title_episode.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "create title crew",
+        sql: include_str!("../migrations/0007_create_title_crew.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "create name search index",
+        sql: include_str!("../migrations/0008_create_name_search_index.sql"),
+    },
+];
+
+/// The schema of the SQLite repository. Applied versions are recorded in the `user_version` pragma.
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create imdb tables",
+        sql: include_str!("../migrations/sqlite/0001_create_imdb_tables.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "create title akas",
+        sql: include_str!("../migrations/sqlite/0002_create_title_akas.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "create title episode",
+        sql: include_str!("../migrations/sqlite/0003_create_title_episode.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "create title crew",
+        sql: include_str!("../migrations/sqlite/0004_create_title_crew.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "add name searchname",
+        sql: include_str!("../migrations/sqlite/0005_add_name_searchname.sql"),
+    },
+];
+
+/// Number of search names filled in per transaction
+const SEARCHNAME_BATCH_SIZE: i64 = 100_000;
+
+/// Arbitrary key of the advisory lock serializing concurrent migration runs
+const MIGRATION_LOCK: i64 = 0x1bdb_c09f;
+
+/// Apply the migrations that are not recorded in `schema_migrations` yet, each in its own transaction.
+/// Returns the number of applied migrations.
+pub async fn run(db_pool: &sqlx::PgPool) -> Result<usize, sqlx::Error> {
+    let mut conn = db_pool.acquire().await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+        version integer PRIMARY KEY,
+        description text NOT NULL,
+        applied_at timestamptz NOT NULL DEFAULT now())",
+    )
+    .await?;
+
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut conn)
+        .await?;
+    let result = apply_pending(&mut conn).await;
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut conn)
+        .await?;
+    result
+}
+
+async fn apply_pending(conn: &mut sqlx::PgConnection) -> Result<usize, sqlx::Error> {
+    let applied = sqlx::query("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|r| r.i32("version"))
+        .collect::<Vec<i32>>();
+
+    let mut count = 0;
+    for migration in MIGRATIONS {
+        if !applied.contains(&migration.version) {
+            let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+            tx.execute(migration.sql).await?;
+            sqlx::query("INSERT INTO schema_migrations (version, description) VALUES ($1, $2)")
+                .bind(migration.version)
+                .bind(migration.description)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            println!(
+                "Applied migration {} - {}",
+                migration.version, migration.description
+            );
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Apply the SQLite migrations newer than the `user_version` of the database, each in its own transaction.
+/// SQLite connections are not shared between processes, so there is no lock to take.
+pub async fn run_sqlite(db_pool: &sqlx::SqlitePool) -> Result<usize, sqlx::Error> {
+    let mut conn = db_pool.acquire().await?;
+    let user_version = sqlx::query("PRAGMA user_version")
+        .fetch_one(&mut conn)
+        .await?
+        .i32("user_version");
+
+    let mut count = 0;
+    for migration in SQLITE_MIGRATIONS {
+        if migration.version > user_version {
+            let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+            tx.execute(migration.sql).await?;
+            // pragmas take no bind parameters
+            tx.execute(format!("PRAGMA user_version = {}", migration.version).as_str())
+                .await?;
+            tx.commit().await?;
+            println!(
+                "Applied migration {} - {}",
+                migration.version, migration.description
+            );
+            count += 1;
+        }
+    }
+    drop(conn);
+    fill_searchnames(db_pool).await?;
+    Ok(count)
+}
+
+/// Fill in the search names of the names without one, e.g. names loaded before the column was added.
+/// SQLite cannot remove accents, so they are computed by `similarity::search_name`.
+pub async fn fill_searchnames(db_pool: &sqlx::SqlitePool) -> Result<u64, sqlx::Error> {
+    let mut count = 0;
+    loop {
+        let names = sqlx::query(
+            "SELECT nconst, primaryname FROM name_basics WHERE searchname IS NULL AND primaryname IS NOT NULL LIMIT ?1",
+        )
+        .bind(SEARCHNAME_BATCH_SIZE)
+        .fetch_all(db_pool)
+        .await?;
+        if names.is_empty() {
+            return Ok(count);
+        }
+        let mut tx = db_pool.begin().await?;
+        for r in &names {
+            sqlx::query("UPDATE name_basics SET searchname = ?1 WHERE nconst = ?2")
+                .bind(similarity::search_name(&r.string("primaryname")))
+                .bind(r.string("nconst"))
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+        count += names.len() as u64;
+    }
+}
+
+/// Bring the database schema up to date before the server starts.
+/// This is an ignite fairing, because liftoff fairings run concurrently and the
+/// `TitlePrincipalCacheLoader` needs the tables to exist. A failed migration aborts the launch.
+pub enum SchemaMigrator {
+    Postgres,
+    Sqlite,
+}
+
+impl SchemaMigrator {
+    pub fn init() -> SchemaMigrator {
+        SchemaMigrator::Postgres
+    }
+
+    pub fn init_sqlite() -> SchemaMigrator {
+        SchemaMigrator::Sqlite
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SchemaMigrator {
+    fn info(&self) -> Info {
+        Info {
+            name: "Apply database schema migrations",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let result = match self {
+            SchemaMigrator::Postgres => match DbPool::fetch(&rocket) {
+                Some(db_pool) => run(db_pool).await,
+                None => return Err(rocket),
+            },
+            SchemaMigrator::Sqlite => match SqliteDbPool::fetch(&rocket) {
+                Some(db_pool) => run_sqlite(db_pool).await,
+                None => return Err(rocket),
+            },
+        };
+        match result {
+            Ok(count) => {
+                println!(
+                    "Database schema is up to date, applied {} migrations",
+                    count
+                );
+                Ok(rocket)
+            }
+            Err(err) => {
+                println!("Error migrating the database schema: {}", err);
+                Err(rocket)
+            }
+        }
+    }
+}
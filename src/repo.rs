@@ -1,103 +1,397 @@
+use std::collections::HashMap;
 use std::future;
+use std::sync::Arc;
 
 use rocket::futures::StreamExt;
-use rocket::http::Status;
 //use rocket_db_pools::sqlx::postgres::PgRow;
 use rocket_db_pools::sqlx::{self, Error, Row};
 
+use crate::error::ApiError;
 use crate::schemas::{
-    NameBasics, TitleBasics, TitleDetails, TitlePrincipal, TitlePrincipalCache, TitleToNames,
+    DbRow, Episode, EpisodeSeries, Filmography, FilmographyTitle, NameBasics, SearchMode,
+    SeriesEpisodes, TitleAka, TitleBasics, TitleCrew, TitleDetails, TitlePrincipal,
+    TitlePrincipalCache, TitleQuery, TitleToNames,
 };
 
-pub async fn titles_by_name(
+/// The queries of the API handlers and the cache loader.
+/// `PostgresRepository` runs them on the database, `MemoryRepository` on TSV files loaded to memory.
+#[rocket::async_trait]
+pub trait MovieRepository: Send + Sync {
+    /// One page of the movies matching the title query, with their principals,
+    /// and the total number of matching movies
+    async fn titles_by_name(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError>;
+
+    /// A title with its rating, principals, crew, alternate titles and, for episodes, its series
+    async fn title_details(&self, tconst: &str) -> Result<TitleDetails, ApiError>;
+
+    /// The names matching `name` with their known-for titles, the busiest first.
+    /// Without wildcard, names match ignoring case, accents and repeated whitespace.
+    async fn basics_for_name(
+        &self,
+        cache: &TitlePrincipalCache,
+        name: &str,
+        use_wildcard: bool,
+    ) -> Result<Vec<NameBasics>, ApiError>;
+
+    /// The primary names of the given names by id. Unknown ids are left out.
+    async fn primary_names(&self, nconsts: &[String]) -> Result<HashMap<String, String>, ApiError>;
+
+    /// The basics of the given titles by id. Unknown ids are left out.
+    async fn title_basics(
+        &self,
+        tconsts: &[String],
+    ) -> Result<HashMap<String, TitleBasics>, ApiError>;
+
+    /// A name with all their titles by category, restricted to the title types if these are given
+    async fn filmography(
+        &self,
+        nconst: &str,
+        title_types: Option<&[String]>,
+    ) -> Result<Filmography, ApiError>;
+
+    /// The title connecting 2 names, with their characters
+    async fn title_to_names(
+        &self,
+        tconst: &str,
+        nconst1: &str,
+        nconst2: &str,
+    ) -> Result<TitleToNames, ApiError>;
+
+    /// The episodes of a series by season, with their ratings
+    async fn series_episodes(&self, tconst: &str) -> Result<SeriesEpisodes, ApiError>;
+
+    /// Identifies the loaded data, to match it with cache snapshots. `None` if the data is not snapshotted.
+    async fn dataset_fingerprint(&self) -> Result<Option<String>, ApiError>;
+
+    /// Load the principals of the given categories and the attributes of their titles into the cache
+    async fn titles_to_principals(&self, cache: &TitlePrincipalCache, categories: &[String]);
+}
+
+/// The repository managed by Rocket, selected by the `repository` setting.
+/// It is shared, for streamed responses to keep using it after their handler returned.
+pub type Repository = Arc<dyn MovieRepository>;
+
+pub struct PostgresRepository {
+    db_pool: sqlx::PgPool,
+}
+
+impl PostgresRepository {
+    pub fn new(db_pool: sqlx::PgPool) -> PostgresRepository {
+        PostgresRepository { db_pool }
+    }
+}
+
+#[rocket::async_trait]
+impl MovieRepository for PostgresRepository {
+    async fn titles_by_name(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+        titles_by_name(&self.db_pool, query).await
+    }
+
+    async fn title_details(&self, tconst: &str) -> Result<TitleDetails, ApiError> {
+        title_details(&self.db_pool, tconst).await
+    }
+
+    async fn basics_for_name(
+        &self,
+        cache: &TitlePrincipalCache,
+        name: &str,
+        use_wildcard: bool,
+    ) -> Result<Vec<NameBasics>, ApiError> {
+        basics_for_name(&self.db_pool, cache, name, use_wildcard).await
+    }
+
+    async fn primary_names(&self, nconsts: &[String]) -> Result<HashMap<String, String>, ApiError> {
+        primary_names(&self.db_pool, nconsts).await
+    }
+
+    async fn title_basics(
+        &self,
+        tconsts: &[String],
+    ) -> Result<HashMap<String, TitleBasics>, ApiError> {
+        title_basics(&self.db_pool, tconsts).await
+    }
+
+    async fn filmography(
+        &self,
+        nconst: &str,
+        title_types: Option<&[String]>,
+    ) -> Result<Filmography, ApiError> {
+        filmography(&self.db_pool, nconst, title_types).await
+    }
+
+    async fn title_to_names(
+        &self,
+        tconst: &str,
+        nconst1: &str,
+        nconst2: &str,
+    ) -> Result<TitleToNames, ApiError> {
+        title_to_names(&self.db_pool, tconst, nconst1, nconst2).await
+    }
+
+    async fn series_episodes(&self, tconst: &str) -> Result<SeriesEpisodes, ApiError> {
+        series_episodes(&self.db_pool, tconst).await
+    }
+
+    async fn dataset_fingerprint(&self) -> Result<Option<String>, ApiError> {
+        dataset_fingerprint(&self.db_pool)
+            .await
+            .map(Some)
+            .map_err(|err| ApiError::database("Error identifying the dataset".to_string(), err))
+    }
+
+    async fn titles_to_principals(&self, cache: &TitlePrincipalCache, categories: &[String]) {
+        titles_to_principals(&self.db_pool, cache, categories).await
+    }
+}
+
+/// Replace the known-for title ids of the names by the titles found, and order the names by their roles
+pub fn with_known_titles(
+    cache: &TitlePrincipalCache,
+    names: Vec<NameBasics>,
+    known_titles: &HashMap<String, TitleBasics>,
+) -> Vec<NameBasics> {
+    let mut new_name_vec: Vec<NameBasics> = Vec::new();
+    for mut name_basics in names {
+        let titles = name_basics
+            .title_ids()
+            .iter()
+            .filter_map(|tconst| known_titles.get(tconst).cloned())
+            .collect();
+        let references = cache.ref_count(&name_basics);
+        name_basics.set_details(references, titles);
+        new_name_vec.push(name_basics);
+    }
+
+    new_name_vec.sort_by_key(|rec| std::cmp::Reverse(rec.actorroles));
+    new_name_vec
+}
+
+/// The fuzzy search relevance of title_basics tb for the fragment $1, see `similarity::title_relevance`.
+/// The full-text document is the expression of the title_basics_fulltext_idx index.
+const RELEVANCE: &str = "round((greatest(similarity(tb.primarytitle, $1), similarity(coalesce(tb.originaltitle, ''), $1))
+    + CASE WHEN to_tsvector('simple', coalesce(tb.primarytitle, '') || ' ' || coalesce(tb.originaltitle, '')) @@ plainto_tsquery('simple', $1) THEN 0.5 ELSE 0 END
+    + CASE WHEN lower(tb.primarytitle) = lower($1) OR lower(tb.originaltitle) = lower($1) THEN 1 ELSE 0 END)::numeric, 4)::float8";
+
+/// The fuzzy search relevance of title_akas ta, like `RELEVANCE`
+const AKA_RELEVANCE: &str = "round((similarity(coalesce(ta.title, ''), $1)
+    + CASE WHEN to_tsvector('simple', coalesce(ta.title, '')) @@ plainto_tsquery('simple', $1) THEN 0.5 ELSE 0 END
+    + CASE WHEN lower(ta.title) = lower($1) THEN 1 ELSE 0 END)::numeric, 4)::float8";
+
+async fn titles_by_name(
     db_pool: &sqlx::PgPool,
-    title_name: &str,
-) -> Result<Vec<TitleDetails>, (Status, String)> {
-    let title_match = format!("%{}%", title_name);
-    let sql = "SELECT tb.*, tr.numvotes, tr.averagerating FROM title_basics tb
+    query: &TitleQuery,
+) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+    let title_name = query.fragment.as_str();
+    let (title_match, matches, aka_matches, relevance, aka_relevance) = match query.mode {
+        SearchMode::Substring => (
+            format!("%{}%", title_name),
+            "tb.primarytitle ilike $1 or tb.originaltitle ilike $1",
+            "ta.title ilike $1",
+            "NULL::float8",
+            "NULL::float8",
+        ),
+        // % is the similarity operator of pg_trgm, all conditions use an index
+        SearchMode::Fuzzy => (
+            title_name.to_string(),
+            "tb.primarytitle % $1 OR tb.originaltitle % $1
+            OR to_tsvector('simple', coalesce(tb.primarytitle, '') || ' ' || coalesce(tb.originaltitle, '')) @@ plainto_tsquery('simple', $1)",
+            "ta.title % $1 OR to_tsvector('simple', coalesce(ta.title, '')) @@ plainto_tsquery('simple', $1)",
+            RELEVANCE,
+            AKA_RELEVANCE,
+        ),
+    };
+    // the titles and alternate titles matching the fragment, then the best match of each title:
+    // the most relevant, else the primary or original title, else the first alternate title
+    let from_where = format!(
+        "FROM (
+        SELECT DISTINCT ON (tconst) * FROM (
+            SELECT tb.tconst, NULL::text aka_title, NULL::text aka_region, NULL::text aka_language, 0 aka_ordering, {} relevance
+            FROM title_basics tb
+            WHERE {}
+            UNION ALL
+            SELECT ta.titleid, ta.title, ta.region, ta.language, ta.ordering, {}
+            FROM title_akas ta
+            WHERE ({}) AND ($2::text IS NULL OR ta.region = $2) AND ($3::text IS NULL OR ta.language = $3)
+        ) matches
+        ORDER BY tconst, relevance DESC NULLS LAST, aka_title IS NOT NULL, aka_ordering
+    ) m
+    JOIN title_basics tb ON tb.tconst = m.tconst
     JOIN title_ratings tr ON tr.tconst = tb.tconst
-    WHERE tb.titletype = 'movie' AND (tb.primarytitle ilike $1 or tb.originaltitle ilike $2)
-    ORDER BY startyear";
-    let titles = sqlx::query(sql)
+    WHERE tb.titletype = ANY($4)",
+        relevance, matches, aka_relevance, aka_matches
+    );
+
+    let sql = format!("SELECT count(*) total {}", from_where);
+    let total: i64 = sqlx::query(&sql)
         .bind(&title_match)
+        .bind(&query.region)
+        .bind(&query.language)
+        .bind(&query.title_types)
+        .fetch_one(db_pool)
+        .await
+        .map(|r| r.get("total"))
+        .map_err(|err| {
+            ApiError::database(format!("Error counting titles named {}", title_name), err)
+        })?;
+    if total == 0 {
+        return Err(ApiError::not_found(
+            title_name,
+            format!("Could not find Title {}", title_name),
+        ));
+    }
+
+    let sql = format!(
+        "SELECT tb.*, tr.numvotes, tr.averagerating, m.relevance, m.aka_title, m.aka_region, m.aka_language
+    {} ORDER BY {} LIMIT $5 OFFSET $6",
+        from_where,
+        query.sort.order_by()
+    );
+    let titles = sqlx::query(&sql)
         .bind(&title_match)
+        .bind(&query.region)
+        .bind(&query.language)
+        .bind(&query.title_types)
+        .bind(query.limit)
+        .bind(query.offset)
         .fetch_all(db_pool)
         .await
-        .and_then(|rows| {
-            let title_vec = rows
-                .iter()
+        .map(|rows| {
+            rows.iter()
                 .map(|r| TitleDetails::from_db_row(r))
-                .collect::<Vec<TitleDetails>>();
-
-            Ok(title_vec)
+                .collect::<Vec<TitleDetails>>()
         });
 
     match titles {
-        Ok(title_vec) => {
-            if title_vec.is_empty() {
-                Err((
-                    Status::NotFound,
-                    format!("Could not find Title {}", title_name),
-                ))
-            } else {
-                let mut titles_with_principals: Vec<TitleDetails> = vec![];
-                for mut t in title_vec {
-                    let principals = principals_by_title(db_pool, t.get_title_id()).await?;
-                    t.add_principals(&principals);
-                    titles_with_principals.push(t);
+        Ok(mut title_vec) => {
+            let tconsts: Vec<String> = title_vec
+                .iter()
+                .map(|t| t.get_title_id().to_string())
+                .collect();
+            let mut principals = principals_by_titles(db_pool, &tconsts).await?;
+            for t in title_vec.iter_mut() {
+                if let Some(title_principals) = principals.remove(t.get_title_id()) {
+                    t.add_principals(&title_principals);
                 }
-                Ok(titles_with_principals)
             }
+            Ok((title_vec, total))
         }
-        Err(err) => Err((
-            Status::InternalServerError,
-            format!("Error querying title {} by name: {:?}", title_name, err),
+        Err(err) => Err(ApiError::database(
+            format!("Error querying title {} by name", title_name),
+            err,
         )),
     }
 }
 
-async fn principals_by_title(
+async fn title_details(db_pool: &sqlx::PgPool, tconst: &str) -> Result<TitleDetails, ApiError> {
+    let db_err = |err| ApiError::database(format!("Error retrieving title {}", tconst), err);
+    let sql = "SELECT tb.*, tr.numvotes, tr.averagerating
+    FROM title_basics tb
+    LEFT JOIN title_ratings tr ON tr.tconst = tb.tconst
+    WHERE tb.tconst = $1";
+    let mut title = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(db_err)?
+        .map(|r| TitleDetails::from_db_row(&r))
+        .ok_or_else(|| ApiError::not_found(tconst, format!("Could not find Title {}", tconst)))?;
+
+    let mut principals = principals_by_titles(db_pool, &[tconst.to_string()]).await?;
+    title.add_principals(&principals.remove(tconst).unwrap_or_default());
+
+    let sql = "SELECT directors, writers FROM title_crew WHERE tconst = $1";
+    let mut crew = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(db_err)?
+        .map(|r| TitleCrew::from_db_row(&r))
+        .unwrap_or_default();
+    crew.set_names(&primary_names(db_pool, &crew.name_ids()).await?);
+
+    let sql = "SELECT title, region, language FROM title_akas WHERE titleid = $1 ORDER BY ordering";
+    let akas = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_all(db_pool)
+        .await
+        .map_err(db_err)?
+        .iter()
+        .map(|r| TitleAka::from_db_row(r))
+        .collect();
+
+    let sql = "SELECT te.parenttconst tconst, tb.titletype, tb.primarytitle, tb.startyear, te.seasonnumber, te.episodenumber
+    FROM title_episode te
+    LEFT JOIN title_basics tb ON tb.tconst = te.parenttconst
+    WHERE te.tconst = $1";
+    let episode = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(db_err)?
+        .map(|r| EpisodeSeries::from_db_row(&r));
+
+    title.add_details(crew, akas, episode);
+    Ok(title)
+}
+
+/// The principals of the titles in billing order, by title id, retrieved in one query
+async fn principals_by_titles(
     db_pool: &sqlx::PgPool,
-    title_id: &str,
-) -> Result<Vec<TitlePrincipal>, (Status, String)> {
-    let sql = "SELECT tp.nconst, tp.category, tp.job, tp.characters , nb.primaryname, nb.birthyear, nb.deathyear
+    title_ids: &[String],
+) -> Result<HashMap<String, Vec<TitlePrincipal>>, ApiError> {
+    let sql = "SELECT tp.tconst, tp.nconst, tp.category, tp.job, tp.characters , nb.primaryname, nb.birthyear, nb.deathyear
     FROM title_principals tp
     JOIN name_basics nb ON nb.nconst = tp.nconst
-    WHERE tconst = $1
-    ORDER BY tp.ordering";
+    WHERE tconst = ANY($1)
+    ORDER BY tp.tconst, tp.ordering";
     sqlx::query(sql)
-        .bind(title_id)
+        .bind(title_ids)
         .fetch_all(db_pool)
         .await
-        .and_then(|rows| {
-            let principals = rows
-                .iter()
-                .map(|r| TitlePrincipal::from_db_row(r))
-                .collect::<Vec<TitlePrincipal>>();
-
-            Ok(principals)
+        .map(|rows| {
+            let mut principals: HashMap<String, Vec<TitlePrincipal>> = HashMap::new();
+            for r in &rows {
+                principals
+                    .entry(r.string("tconst"))
+                    .or_default()
+                    .push(TitlePrincipal::from_db_row(r));
+            }
+            principals
         })
         .map_err(|err| {
-            (
-                Status::InternalServerError,
+            ApiError::database(
                 format!(
-                    "Error retrieving title principals for title id {}: {:?}",
-                    title_id, err
+                    "Error retrieving title principals for title ids {:?}",
+                    title_ids
                 ),
+                err,
             )
         })
 }
 
-pub async fn titles_to_principals(db_pool: &sqlx::PgPool, cache: &TitlePrincipalCache) {
-    let sql = "SELECT tconst, nconst FROM title_principals tp where category = 'actor'";
+async fn titles_to_principals(
+    db_pool: &sqlx::PgPool,
+    cache: &TitlePrincipalCache,
+    categories: &[String],
+) {
+    cache.insert_categories(categories);
+    let sql = "SELECT tconst, nconst, category FROM title_principals tp WHERE category = ANY($1)";
     sqlx::query(sql)
+        .bind(categories)
         .fetch(db_pool)
         .for_each(|result| {
             match result {
                 Ok(row) => {
-                    let tconst = row.get::<String, usize>(0);
-                    let nconst = row.get::<String, usize>(1);
-                    cache.insert(tconst, nconst);
+                    let tconst = row.get::<&str, usize>(0);
+                    let nconst = row.get::<&str, usize>(1);
+                    let category = row.get::<&str, usize>(2);
+                    cache.insert(tconst, nconst, category);
                 }
                 Err(error) => {
                     println!("Error reading DB row: {}", error);
@@ -106,14 +400,116 @@ pub async fn titles_to_principals(db_pool: &sqlx::PgPool, cache: &TitlePrincipal
             future::ready(())
         })
         .await;
+
+    let sql = "SELECT tb.tconst, tb.titletype, tb.startyear, tb.genres, tr.numvotes, tr.averagerating
+    FROM title_basics tb
+    LEFT JOIN title_ratings tr ON tr.tconst = tb.tconst
+    WHERE EXISTS (SELECT 1 FROM title_principals tp WHERE tp.tconst = tb.tconst AND tp.category = ANY($1))";
+    sqlx::query(sql)
+        .bind(categories)
+        .fetch(db_pool)
+        .for_each(|result| {
+            match result {
+                Ok(row) => cache.insert_title(&TitleDetails::from_db_row(&row)),
+                Err(error) => {
+                    println!("Error reading DB row: {}", error);
+                }
+            }
+            future::ready(())
+        })
+        .await;
+}
+
+async fn filmography(
+    db_pool: &sqlx::PgPool,
+    nconst: &str,
+    title_types: Option<&[String]>,
+) -> Result<Filmography, ApiError> {
+    let db_err = |err| {
+        ApiError::database(
+            format!("Error retrieving the filmography of {}", nconst),
+            err,
+        )
+    };
+    let sql = "SELECT nconst, primaryname, primaryprofession, birthyear, deathyear FROM name_basics WHERE nconst = $1";
+    let name = sqlx::query(sql)
+        .bind(nconst)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(db_err)?
+        .ok_or_else(|| ApiError::not_found(nconst, format!("Could not find Name {}", nconst)))?;
+
+    let sql = "SELECT tp.category, tp.job, tp.characters, tb.tconst, tb.titletype, tb.primarytitle, tb.startyear, tr.averagerating, tr.numvotes
+    FROM title_principals tp
+    JOIN title_basics tb ON tb.tconst = tp.tconst
+    LEFT JOIN title_ratings tr ON tr.tconst = tp.tconst
+    WHERE tp.nconst = $1 AND ($2::text[] IS NULL OR tb.titletype = ANY($2))";
+    let titles = sqlx::query(sql)
+        .bind(nconst)
+        .bind(title_types)
+        .fetch_all(db_pool)
+        .await
+        .map_err(db_err)?
+        .iter()
+        .map(|r| (r.string("category"), FilmographyTitle::from_db_row(r)))
+        .collect();
+    Ok(Filmography::new(&name, titles))
+}
+
+async fn series_episodes(db_pool: &sqlx::PgPool, tconst: &str) -> Result<SeriesEpisodes, ApiError> {
+    let db_err =
+        |err| ApiError::database(format!("Error retrieving the episodes of {}", tconst), err);
+    let sql =
+        "SELECT tconst, titletype, primarytitle, startyear FROM title_basics WHERE tconst = $1";
+    let series = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(db_err)?
+        .map(|r| TitleBasics::from_db_row(&r))
+        .ok_or_else(|| ApiError::not_found(tconst, format!("Could not find Title {}", tconst)))?;
+
+    let sql = "SELECT te.tconst, te.seasonnumber, te.episodenumber, tb.primarytitle, tb.startyear, tr.averagerating, tr.numvotes
+    FROM title_episode te
+    LEFT JOIN title_basics tb ON tb.tconst = te.tconst
+    LEFT JOIN title_ratings tr ON tr.tconst = te.tconst
+    WHERE te.parenttconst = $1";
+    let episodes = sqlx::query(sql)
+        .bind(tconst)
+        .fetch_all(db_pool)
+        .await
+        .map_err(db_err)?
+        .iter()
+        .map(|r| (r.opt_i32("seasonnumber"), Episode::from_db_row(r)))
+        .collect();
+    Ok(SeriesEpisodes::new(series, episodes))
+}
+
+/// Identifies the content of the title_principals table, to match it with cache snapshots.
+/// Based on the last recorded import, or the row count if the data was not loaded by the import subcommand.
+async fn dataset_fingerprint(db_pool: &sqlx::PgPool) -> Result<String, Error> {
+    let sql =
+        "SELECT id, imported_at::text imported_at FROM dataset_imports ORDER BY id DESC LIMIT 1";
+    let last_import = sqlx::query(sql).fetch_optional(db_pool).await?;
+    if let Some(r) = last_import {
+        Ok(format!(
+            "import:{}:{}",
+            r.i32("id"),
+            r.string("imported_at")
+        ))
+    } else {
+        let sql = "SELECT count(*)::integer cnt FROM title_principals";
+        let r = sqlx::query(sql).fetch_one(db_pool).await?;
+        Ok(format!("rows:{}", r.i32("cnt")))
+    }
 }
 
-pub async fn title_to_names(
+async fn title_to_names(
     db_pool: &sqlx::PgPool,
     tconst: &str,
     nconst1: &str,
     nconst2: &str,
-) -> Result<TitleToNames, (Status, String)> {
+) -> Result<TitleToNames, ApiError> {
     let sql = "SELECT tb.tconst, tb.primarytitle, tb.startyear, tb.titletype, tp1.nconst nconst1, tp1.characters characters1, nb1.primaryname primaryname1, tp2.nconst nconst2, tp2.characters characters2, nb2.primaryname primaryname2
     FROM title_basics tb 
     JOIN title_principals tp1 ON tp1.tconst = tb.tconst AND tp1.nconst = $1 
@@ -127,31 +523,29 @@ pub async fn title_to_names(
         .bind(tconst)
         .fetch_one(db_pool)
         .await
-        .and_then(|r| {
-            let title_to_names = TitleToNames::from_db_row(&r);
-            Ok(title_to_names)
-        })
+        .map(|r| TitleToNames::from_db_row(&r))
         .map_err(|err| {
-            (
-                Status::InternalServerError,
+            ApiError::database(
                 format!(
-                    "Error retrieving title {} and principals {}, {}: {:?}",
-                    tconst, nconst1, nconst2, err
+                    "Error retrieving title {} and principals {}, {}",
+                    tconst, nconst1, nconst2
                 ),
+                err,
             )
         })
 }
 
-pub async fn basics_for_name(
+async fn basics_for_name(
     db_pool: &sqlx::PgPool,
     cache: &TitlePrincipalCache,
     name: &str,
     use_wildcard: bool,
-) -> Result<Vec<NameBasics>, Error> {
+) -> Result<Vec<NameBasics>, ApiError> {
+    // exact names match ignoring case, accents and repeated whitespace, using name_basics_searchname_idx
     let where_clause = if use_wildcard {
         "primaryname like $1"
     } else {
-        "primaryname = $1"
+        "imdb_searchname(primaryname) = imdb_searchname($1)"
     };
     let sql = format!(
         "SELECT nconst, primaryname, primaryprofession, birthyear, deathyear, knownfortitles FROM name_basics WHERE {}",
@@ -161,86 +555,49 @@ pub async fn basics_for_name(
         .bind(name)
         .fetch_all(db_pool)
         .await
-        .and_then(|rows| {
-            let name_vec = rows
-                .iter()
+        .map(|rows| {
+            rows.iter()
                 .map(|r| NameBasics::from_db_row(r))
-                .collect::<Vec<NameBasics>>();
-
-            Ok(name_vec)
-        })?;
-
-    let mut new_name_vec: Vec<NameBasics> = Vec::new();
-    for mut name_basics in name_vec {
-        let tconsts = name_basics.title_ids();
-        // this is prone to SQL injection, but unfortunately sqlx 0.5 has no prepared statement solution for this
-        let sql = format!(
-            "SELECT * FROM title_basics WHERE tconst in ( {} )",
-            mk_string(&tconsts, "'", "', '", "'")
-        );
-
-        let titles = sqlx::query(&sql)
-            .fetch_all(db_pool)
-            .await
-            .and_then(|rows| {
-                let title_vec = rows
-                    .iter()
-                    .map(|r| TitleBasics::from_db_row(r))
-                    .collect::<Vec<TitleBasics>>();
-
-                Ok(title_vec)
-            })?;
-
-        let references = cache.ref_count(&name_basics);
-        name_basics.set_details(references, titles);
-        new_name_vec.push(name_basics);
-    }
-
-    new_name_vec.sort_by(|rec1, rec2| rec2.actorroles.cmp(&rec1.actorroles));
+                .collect::<Vec<NameBasics>>()
+        })
+        .map_err(|err| ApiError::database(format!("Error searching for name {}", name), err))?;
 
-    Ok(new_name_vec)
+    let tconsts: Vec<String> = name_vec.iter().flat_map(|n| n.title_ids()).collect();
+    let known_titles = title_basics(db_pool, &tconsts).await?;
+    Ok(with_known_titles(cache, name_vec, &known_titles))
 }
 
-pub async fn nconst_for_name(
+async fn title_basics(
     db_pool: &sqlx::PgPool,
-    name: &str,
-) -> Result<Vec<String>, (Status, String)> {
-    let sql = "SELECT nconst FROM name_basics WHERE primaryname = $1";
-    let result = sqlx::query(sql)
-        .bind(name)
+    tconsts: &[String],
+) -> Result<HashMap<String, TitleBasics>, ApiError> {
+    // the ids come from the database or the graph, but are bound as an array rather than trusted in the SQL text
+    let sql = "SELECT tconst, titletype, primarytitle, startyear FROM title_basics WHERE tconst = ANY($1)";
+    sqlx::query(sql)
+        .bind(tconsts)
         .fetch_all(db_pool)
         .await
-        .and_then(|rows| {
-            let name_vec = rows
-                .iter()
-                .map(|r| r.get::<String, &str>("nconst"))
-                .collect::<Vec<String>>();
-
-            Ok(name_vec)
-        });
-    match result {
-        Ok(ok) => Ok(ok),
-        Err(err) => Err((
-            Status::InternalServerError,
-            format!("Error searching for name {}: {:?}", name, err),
-        )),
-    }
-}
-
-fn mk_string_simple(list: &Vec<String>, delimiter: &str) -> String {
-    let mut str = String::new();
-    let mut dl = "";
-    for s in list {
-        str.push_str(dl);
-        str.push_str(&s);
-        dl = delimiter;
-    }
-    str
+        .map(|rows| {
+            rows.iter()
+                .map(|r| (r.string("tconst"), TitleBasics::from_db_row(r)))
+                .collect()
+        })
+        .map_err(|err| ApiError::database("Error retrieving titles".to_string(), err))
 }
 
-fn mk_string(list: &Vec<String>, start: &str, delimiter: &str, end: &str) -> String {
-    let mut str = String::from(start);
-    str.push_str(&mk_string_simple(list, delimiter));
-    str.push_str(end);
-    str
+async fn primary_names(
+    db_pool: &sqlx::PgPool,
+    nconsts: &[String],
+) -> Result<HashMap<String, String>, ApiError> {
+    let sql = "SELECT nconst, primaryname FROM name_basics WHERE nconst = ANY($1)";
+    sqlx::query(sql)
+        .bind(nconsts)
+        .fetch_all(db_pool)
+        .await
+        .map(|rows| {
+            rows.iter()
+                .filter_map(|r| Some((r.string("nconst"), r.opt_string("primaryname")?)))
+                .collect()
+        })
+        .map_err(|err| ApiError::database("Error retrieving names".to_string(), err))
 }
@@ -1,13 +1,21 @@
-use dashmap::DashMap;
+// the FromForm derive of rocket 0.5.0-rc.2 allows the removed `private_in_public` lint
+#![allow(renamed_and_removed_lints)]
 
-use dashmap::mapref::one::Ref;
 use rocket::serde::{Deserialize, Serialize};
+use rocket::{FromForm, FromFormField};
 
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 
-use std::collections::HashSet;
+use std::cmp;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::graph::{ActorGraph, GraphBuilder};
+use crate::kevinbacon::EgoNetwork;
+use crate::similarity;
 
 pub trait DbRow {
     fn string(&self, column: &str) -> String;
@@ -18,7 +26,7 @@ pub trait DbRow {
     fn opt_f64(&self, column: &str) -> Option<f64>;
 }
 
-use rocket_db_pools::sqlx::{postgres::PgRow, Row};
+use rocket_db_pools::sqlx::{postgres::PgRow, sqlite::SqliteRow, Row};
 impl DbRow for PgRow {
     fn string(&self, column: &str) -> String {
         self.get::<String, &str>(column)
@@ -40,13 +48,35 @@ impl DbRow for PgRow {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+// SQLite decodes NULL as zero or an empty string, so optional values are decoded as `Option`
+impl DbRow for SqliteRow {
+    fn string(&self, column: &str) -> String {
+        self.get::<String, &str>(column)
+    }
+    fn i32(&self, column: &str) -> i32 {
+        self.get::<i32, &str>(column)
+    }
+    fn bool(&self, column: &str) -> bool {
+        self.try_get::<bool, &str>(column).unwrap_or(false)
+    }
+    fn opt_string(&self, column: &str) -> Option<String> {
+        self.try_get::<Option<String>, &str>(column).ok().flatten()
+    }
+    fn opt_i32(&self, column: &str) -> Option<i32> {
+        self.try_get::<Option<i32>, &str>(column).ok().flatten()
+    }
+    fn opt_f64(&self, column: &str) -> Option<f64> {
+        self.try_get::<Option<f64>, &str>(column).ok().flatten()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
 #[serde(crate = "rocket::serde")]
 pub struct TitleBasics {
     tconst: String,
-    titletype: Option<String>,
-    primarytitle: Option<String>,
-    startyear: Option<i32>,
+    pub titletype: Option<String>,
+    pub primarytitle: Option<String>,
+    pub startyear: Option<i32>,
 }
 
 impl TitleBasics {
@@ -82,7 +112,126 @@ pub struct TitleDetails {
     runtimeminutes: Option<i32>,
     genres: Option<String>,
     isadult: bool,
+    /// how well the title matches a fuzzy search, higher is better
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relevance: Option<f64>,
+    /// the alternate title matching the search, if the primary and original titles match less well
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aka: Option<TitleAka>,
     principals: Vec<TitlePrincipal>,
+    /// the directors and writers, in title details only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crew: Option<TitleCrew>,
+    /// the alternate titles in title.akas order, in title details only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    akas: Option<Vec<TitleAka>>,
+    /// the series of an episode, in title details only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode: Option<EpisodeSeries>,
+}
+
+/// An alternate title from title.akas, localized for a region or language
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct TitleAka {
+    title: String,
+    region: Option<String>,
+    language: Option<String>,
+}
+
+impl TitleAka {
+    pub fn from_db_row(r: &dyn DbRow) -> TitleAka {
+        TitleAka {
+            title: r.string("title"),
+            region: r.opt_string("region"),
+            language: r.opt_string("language"),
+        }
+    }
+
+    /// Whether the alternate title is for the region and language, if these are given
+    pub fn is_for(&self, region: Option<&str>, language: Option<&str>) -> bool {
+        region.is_none_or(|region| self.region.as_deref() == Some(region))
+            && language.is_none_or(|language| self.language.as_deref() == Some(language))
+    }
+}
+
+/// A director or writer of a title
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CrewMember {
+    nconst: String,
+    primaryname: Option<String>,
+}
+
+/// The directors and writers of a title, from title.crew
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TitleCrew {
+    directors: Vec<CrewMember>,
+    writers: Vec<CrewMember>,
+}
+
+impl TitleCrew {
+    pub fn from_db_row(r: &dyn DbRow) -> TitleCrew {
+        let members = |column: &str| {
+            id_list(r.opt_string(column))
+                .into_iter()
+                .map(|nconst| CrewMember {
+                    nconst,
+                    primaryname: None,
+                })
+                .collect()
+        };
+        TitleCrew {
+            directors: members("directors"),
+            writers: members("writers"),
+        }
+    }
+
+    pub fn name_ids(&self) -> Vec<String> {
+        self.directors
+            .iter()
+            .chain(&self.writers)
+            .map(|member| member.nconst.to_string())
+            .collect()
+    }
+
+    /// Add the names found in the database to the crew ids
+    pub fn set_names(&mut self, names: &HashMap<String, String>) {
+        for member in self.directors.iter_mut().chain(&mut self.writers) {
+            member.primaryname = names.get(&member.nconst).cloned();
+        }
+    }
+}
+
+/// The series of an episode, with the season and episode numbers
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EpisodeSeries {
+    series: TitleBasics,
+    seasonnumber: Option<i32>,
+    episodenumber: Option<i32>,
+}
+
+impl EpisodeSeries {
+    /// From the title_basics columns of the series and the title_episode numbers
+    pub fn from_db_row(r: &dyn DbRow) -> EpisodeSeries {
+        EpisodeSeries {
+            series: TitleBasics::from_db_row(r),
+            seasonnumber: r.opt_i32("seasonnumber"),
+            episodenumber: r.opt_i32("episodenumber"),
+        }
+    }
+}
+
+/// The ids of a comma-separated list column
+fn id_list(list: Option<String>) -> Vec<String> {
+    list.iter()
+        .flat_map(|list| list.split(','))
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect()
 }
 
 impl TitleDetails {
@@ -98,19 +247,304 @@ impl TitleDetails {
             runtimeminutes: r.opt_i32("runtimeminutes"),
             genres: r.opt_string("genres"),
             isadult: r.bool("isadult"),
+            relevance: r.opt_f64("relevance"),
+            aka: r.opt_string("aka_title").map(|title| TitleAka {
+                title,
+                region: r.opt_string("aka_region"),
+                language: r.opt_string("aka_language"),
+            }),
             principals: vec![],
+            crew: None,
+            akas: None,
+            episode: None,
+        }
+    }
+
+    /// Match the title and its alternate titles, in billing order, with a title search.
+    /// `None` if neither matches. Substring searches ignore case, and report the first matching
+    /// alternate title if the primary and original titles do not match. Fuzzy searches score
+    /// the title by its most relevant title, which is reported if it is an alternate title.
+    pub fn matching(mut self, query: &TitleQuery, akas: &[TitleAka]) -> Option<TitleDetails> {
+        let fragment = query.fragment.as_str();
+        match query.mode {
+            SearchMode::Substring => {
+                let fragment = fragment.to_lowercase();
+                let contains = |title: &str| title.to_lowercase().contains(&fragment);
+                let titles = [&self.primarytitle, &self.originaltitle];
+                if !titles.into_iter().flatten().any(|title| contains(title)) {
+                    self.aka = Some(akas.iter().find(|aka| contains(&aka.title))?.clone());
+                }
+            }
+            SearchMode::Fuzzy => {
+                self.relevance = similarity::title_relevance(
+                    fragment,
+                    self.primarytitle.as_deref(),
+                    self.originaltitle.as_deref(),
+                );
+                for aka in akas {
+                    let relevance = similarity::title_relevance(fragment, Some(&aka.title), None);
+                    if relevance > self.relevance {
+                        self.relevance = relevance;
+                        self.aka = Some(aka.clone());
+                    }
+                }
+                self.relevance?;
+            }
         }
+        Some(self)
     }
 
     pub fn get_title_id(&self) -> &str {
         &self.tconst
     }
 
-    pub fn add_principals(&mut self, principals: &Vec<TitlePrincipal>) {
-        self.principals = principals.clone();
+    pub fn add_principals(&mut self, principals: &[TitlePrincipal]) {
+        self.principals = principals.to_vec();
+    }
+
+    /// Add the crew, alternate titles and series of the title, for the title details
+    pub fn add_details(
+        &mut self,
+        crew: TitleCrew,
+        akas: Vec<TitleAka>,
+        episode: Option<EpisodeSeries>,
+    ) {
+        self.crew = Some(crew);
+        self.akas = Some(akas);
+        self.episode = episode;
+    }
+}
+
+/// How the fragment of a title search matches titles
+#[derive(Clone, Copy, Debug, Default, PartialEq, FromFormField, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// titles containing the fragment, ignoring case
+    #[default]
+    Substring,
+    /// titles similar to the fragment or containing its words, scored by relevance
+    Fuzzy,
+}
+
+/// The order of title search results. Years and titles ascend, ratings, votes and relevance descend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, FromFormField, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleSort {
+    #[default]
+    Year,
+    Rating,
+    Votes,
+    Title,
+    /// fuzzy searches only, equally relevant titles are ordered by votes
+    Relevance,
+}
+
+impl TitleSort {
+    /// The ORDER BY clause of title_basics tb joined with title_ratings tr.
+    /// Ties are ordered by tconst, so pages do not overlap.
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            TitleSort::Year => "tb.startyear NULLS LAST, tb.tconst",
+            TitleSort::Rating => "tr.averagerating DESC NULLS LAST, tb.tconst",
+            TitleSort::Votes => "tr.numvotes DESC NULLS LAST, tb.tconst",
+            TitleSort::Title => "tb.primarytitle, tb.tconst",
+            TitleSort::Relevance => "relevance DESC, tr.numvotes DESC NULLS LAST, tb.tconst",
+        }
+    }
+
+    /// Compare titles like `order_by` orders them
+    pub fn compare(&self, a: &TitleDetails, b: &TitleDetails) -> cmp::Ordering {
+        match self {
+            TitleSort::Year => nulls_last(a.startyear, b.startyear, false),
+            TitleSort::Rating => nulls_last(a.averagerating, b.averagerating, true),
+            TitleSort::Votes => nulls_last(a.numvotes, b.numvotes, true),
+            TitleSort::Title => nulls_last(a.primarytitle.as_ref(), b.primarytitle.as_ref(), false),
+            TitleSort::Relevance => nulls_last(a.relevance, b.relevance, true)
+                .then_with(|| nulls_last(a.numvotes, b.numvotes, true)),
+        }
+        .then_with(|| a.tconst.cmp(&b.tconst))
+    }
+}
+
+/// Order like `ORDER BY ... NULLS LAST`
+fn nulls_last<T: PartialOrd>(a: Option<T>, b: Option<T>, descending: bool) -> cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(&b).unwrap_or(cmp::Ordering::Equal);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+        (Some(_), None) => cmp::Ordering::Less,
+        (None, Some(_)) => cmp::Ordering::Greater,
+        (None, None) => cmp::Ordering::Equal,
+    }
+}
+
+/// An episode of a series, with its rating
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Episode {
+    tconst: String,
+    episodenumber: Option<i32>,
+    primarytitle: Option<String>,
+    startyear: Option<i32>,
+    averagerating: Option<f64>,
+    numvotes: Option<i32>,
+}
+
+impl Episode {
+    pub fn from_db_row(r: &dyn DbRow) -> Episode {
+        Episode {
+            tconst: r.string("tconst"),
+            episodenumber: r.opt_i32("episodenumber"),
+            primarytitle: r.opt_string("primarytitle"),
+            startyear: r.opt_i32("startyear"),
+            averagerating: r.opt_f64("averagerating"),
+            numvotes: r.opt_i32("numvotes"),
+        }
+    }
+}
+
+/// The episodes of a season in episode order. Episodes without season number are in a season without number.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Season {
+    seasonnumber: Option<i32>,
+    episodes: Vec<Episode>,
+}
+
+/// A series with its episodes, by season
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SeriesEpisodes {
+    series: TitleBasics,
+    seasons: Vec<Season>,
+}
+
+impl SeriesEpisodes {
+    /// Group the episodes by season number, seasons and episodes without number last
+    pub fn new(series: TitleBasics, mut episodes: Vec<(Option<i32>, Episode)>) -> SeriesEpisodes {
+        episodes.sort_by(|(season_a, a), (season_b, b)| {
+            nulls_last(*season_a, *season_b, false)
+                .then_with(|| nulls_last(a.episodenumber, b.episodenumber, false))
+                .then_with(|| a.tconst.cmp(&b.tconst))
+        });
+        let mut seasons: Vec<Season> = Vec::new();
+        for (seasonnumber, episode) in episodes {
+            match seasons.last_mut() {
+                Some(season) if season.seasonnumber == seasonnumber => {
+                    season.episodes.push(episode)
+                }
+                _ => seasons.push(Season {
+                    seasonnumber,
+                    episodes: vec![episode],
+                }),
+            }
+        }
+        SeriesEpisodes { series, seasons }
+    }
+}
+
+/// A title of a filmography, with the role of the contributor
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct FilmographyTitle {
+    tconst: String,
+    titletype: Option<String>,
+    primarytitle: Option<String>,
+    startyear: Option<i32>,
+    averagerating: Option<f64>,
+    numvotes: Option<i32>,
+    job: Option<String>,
+    characters: Option<String>,
+}
+
+impl FilmographyTitle {
+    pub fn from_db_row(r: &dyn DbRow) -> FilmographyTitle {
+        FilmographyTitle {
+            tconst: r.string("tconst"),
+            titletype: r.opt_string("titletype"),
+            primarytitle: r.opt_string("primarytitle"),
+            startyear: r.opt_i32("startyear"),
+            averagerating: r.opt_f64("averagerating"),
+            numvotes: r.opt_i32("numvotes"),
+            job: r.opt_string("job"),
+            characters: r.opt_string("characters"),
+        }
+    }
+}
+
+/// The titles of a contributor in one principal category, by year
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CategoryTitles {
+    category: String,
+    titles: Vec<FilmographyTitle>,
+}
+
+/// A contributor with all their titles, by principal category
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Filmography {
+    nconst: String,
+    primaryname: Option<String>,
+    primaryprofession: Option<String>,
+    birthyear: Option<i32>,
+    deathyear: Option<i32>,
+    categories: Vec<CategoryTitles>,
+}
+
+impl Filmography {
+    /// Group the titles by category, the category with most titles first.
+    /// Titles are ordered by year, titles without year last.
+    pub fn new(name: &dyn DbRow, mut titles: Vec<(String, FilmographyTitle)>) -> Filmography {
+        titles.sort_by(|(category_a, a), (category_b, b)| {
+            category_a
+                .cmp(category_b)
+                .then_with(|| nulls_last(a.startyear, b.startyear, false))
+                .then_with(|| a.tconst.cmp(&b.tconst))
+        });
+        let mut categories: Vec<CategoryTitles> = Vec::new();
+        for (category, title) in titles {
+            match categories.last_mut() {
+                Some(last) if last.category == category => last.titles.push(title),
+                _ => categories.push(CategoryTitles {
+                    category,
+                    titles: vec![title],
+                }),
+            }
+        }
+        // stable, categories with as many titles stay in name order
+        categories.sort_by_key(|category| cmp::Reverse(category.titles.len()));
+        Filmography {
+            nconst: name.string("nconst"),
+            primaryname: name.opt_string("primaryname"),
+            primaryprofession: name.opt_string("primaryprofession"),
+            birthyear: name.opt_i32("birthyear"),
+            deathyear: name.opt_i32("deathyear"),
+            categories,
+        }
     }
 }
 
+/// A title search: the fragment, how it matches titles, and the page of results
+pub struct TitleQuery {
+    pub fragment: String,
+    pub mode: SearchMode,
+    /// restrict the alternate titles searched to a region, e.g. FR
+    pub region: Option<String>,
+    /// restrict the alternate titles searched to a language, e.g. fr
+    pub language: Option<String>,
+    /// the title types searched, e.g. movie or tvSeries
+    pub title_types: Vec<String>,
+    pub sort: TitleSort,
+    pub limit: i64,
+    pub offset: i64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct TitlePrincipal {
@@ -175,7 +609,7 @@ impl TitleToNames {
     }
 }
 
-#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
 #[serde(crate = "rocket::serde")]
 pub struct NameBasics {
     nconst: String,
@@ -189,11 +623,10 @@ pub struct NameBasics {
 
 impl NameBasics {
     pub fn from_db_row(r: &dyn DbRow) -> NameBasics {
-        let mut knownfortitles: Vec<TitleBasics> = vec![];
-        if let Some(kt) = r.opt_string("knownfortitles") {
-            kt.split(",")
-                .for_each(|tconst| knownfortitles.push(TitleBasics::from_tconst(tconst)));
-        }
+        let knownfortitles = id_list(r.opt_string("knownfortitles"))
+            .iter()
+            .map(|tconst| TitleBasics::from_tconst(tconst))
+            .collect();
         NameBasics {
             nconst: r.string("nconst"),
             actorroles: 0,
@@ -201,10 +634,14 @@ impl NameBasics {
             primaryprofession: r.opt_string("primaryprofession"),
             birthyear: r.opt_i32("birthyear"),
             deathyear: r.opt_i32("deathyear"),
-            knownfortitles: knownfortitles,
+            knownfortitles,
         }
     }
 
+    pub fn get_name_id(&self) -> &str {
+        &self.nconst
+    }
+
     pub fn title_ids(&self) -> Vec<String> {
         self.knownfortitles
             .iter()
@@ -212,65 +649,359 @@ impl NameBasics {
             .collect()
     }
 
+    /// Replace the known-for title ids by the titles found in the database
     pub fn set_details(&mut self, references: usize, titles: Vec<TitleBasics>) {
         self.actorroles = references;
         self.knownfortitles = titles;
     }
 }
 
+/// How far a degrees of separation search got
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct SearchStats {
+    /// the degrees explored from both names together
+    pub levels: usize,
+    pub names_visited: usize,
+    pub elapsed: Duration,
+}
+
+/// A name id with its primary name, if the database has it
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct PrincipalName {
+    pub nconst: String,
+    pub primaryname: Option<String>,
+}
+
+impl PrincipalName {
+    pub fn new(nconst: &str, names: &HashMap<String, String>) -> PrincipalName {
+        PrincipalName {
+            nconst: nconst.to_string(),
+            primaryname: names.get(nconst).cloned(),
+        }
+    }
+}
+
+/// The number of names at each degree of separation from a center name, connected through actors and actresses
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct SeparationHistogram {
+    pub center: PrincipalName,
+    /// the number of names at each degree, starting with the center at degree 0
+    pub counts: Vec<usize>,
+    /// the number of names connected to the center, without it
+    pub reachable: usize,
+    /// the average degree of the connected names, 0 if there are none
+    pub average_degree: f64,
+    /// the degree of the farthest connected names
+    pub max_degree: usize,
+    /// the farthest connected names in id order, at most 20
+    pub farthest: Vec<PrincipalName>,
+}
+
+impl SeparationHistogram {
+    pub fn new(
+        nconst: &str,
+        counts: Vec<usize>,
+        farthest: &[String],
+        names: &HashMap<String, String>,
+    ) -> SeparationHistogram {
+        let reachable: usize = counts.iter().skip(1).sum();
+        let total_degree: usize = counts
+            .iter()
+            .enumerate()
+            .map(|(degree, count)| degree * count)
+            .sum();
+        SeparationHistogram {
+            center: PrincipalName::new(nconst, names),
+            reachable,
+            average_degree: if reachable == 0 {
+                0.0
+            } else {
+                total_degree as f64 / reachable as f64
+            },
+            max_degree: counts.len() - 1,
+            counts,
+            farthest: farthest
+                .iter()
+                .map(|nconst| PrincipalName::new(nconst, names))
+                .collect(),
+        }
+    }
+}
+
+/// The kind of a network node
+#[derive(Clone, Copy, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum NodeKind {
+    Name,
+    Title,
+}
+
+/// A name or a title of a network
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NetworkNode {
+    /// the nconst of a name or the tconst of a title
+    id: String,
+    kind: NodeKind,
+    /// the primary name or title
+    label: Option<String>,
+    /// the degree of separation of a name from the center
+    #[serde(skip_serializing_if = "Option::is_none")]
+    degree: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    titletype: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    startyear: Option<i32>,
+}
+
+/// A role of a name in a title of a network
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct NetworkEdge {
+    /// the nconst of the name
+    source: String,
+    /// the tconst of the title
+    target: String,
+}
+
+/// The names within some degrees of a center name and the titles connecting them, as a node and edge list.
+/// Names and titles are both nodes, every edge goes from a name to a title.
+#[derive(Clone, Debug, Serialize, JsonSchema, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Network {
+    center: String,
+    depth: usize,
+    /// the names, nearest to the center first, then the titles
+    nodes: Vec<NetworkNode>,
+    edges: Vec<NetworkEdge>,
+    /// some names within the depth were left out, because of the node limit
+    truncated: bool,
+}
+
+impl Network {
+    pub fn new(
+        center: &str,
+        depth: usize,
+        network: EgoNetwork,
+        names: &HashMap<String, String>,
+        titles: &HashMap<String, TitleBasics>,
+    ) -> Network {
+        let mut nodes: Vec<NetworkNode> = network
+            .names
+            .into_iter()
+            .map(|(nconst, degree)| NetworkNode {
+                label: names.get(&nconst).cloned(),
+                id: nconst,
+                kind: NodeKind::Name,
+                degree: Some(degree),
+                titletype: None,
+                startyear: None,
+            })
+            .collect();
+        let mut edges = Vec::new();
+        for (tconst, nconsts) in network.titles {
+            let basics = titles.get(&tconst);
+            edges.extend(nconsts.into_iter().map(|nconst| NetworkEdge {
+                source: nconst,
+                target: tconst.clone(),
+            }));
+            nodes.push(NetworkNode {
+                label: basics.and_then(|basics| basics.primarytitle.clone()),
+                titletype: basics.and_then(|basics| basics.titletype.clone()),
+                startyear: basics.and_then(|basics| basics.startyear),
+                id: tconst,
+                kind: NodeKind::Title,
+                degree: None,
+            });
+        }
+        Network {
+            center: center.to_string(),
+            depth,
+            nodes,
+            edges,
+            truncated: network.truncated,
+        }
+    }
+}
+
+/// Restricts the titles that connect actors in a degrees of separation search
+#[derive(Debug, Default, FromForm, JsonSchema)]
+pub struct TitleFilterParams {
+    /// Comma-separated list of principal categories connecting through titles, actor,actress by default.
+    /// E.g. director,actor also connects actors through the directors they worked with
+    pub categories: Option<String>,
+    /// Comma-separated list of title types, e.g. movie,tvMovie
+    pub titletype: Option<String>,
+    /// Earliest start year
+    pub startyear_from: Option<i32>,
+    /// Latest start year
+    pub startyear_to: Option<i32>,
+    /// Comma-separated list of genres, titles with any of them are accepted
+    pub genres: Option<String>,
+    /// Minimum number of votes
+    pub min_numvotes: Option<i32>,
+    /// Minimum average rating
+    pub min_averagerating: Option<f64>,
+}
+
+/// The separation histograms kept by the cache, the least recently used are dropped beyond it
+const MAX_HISTOGRAMS: usize = 1000;
+
+/// The most recently used separation histograms, by center
+#[derive(Debug)]
+struct HistogramCache {
+    capacity: usize,
+    /// the histograms with the use count when they were last used
+    histograms: HashMap<String, (SeparationHistogram, u64)>,
+    uses: u64,
+}
+
+impl HistogramCache {
+    fn new(capacity: usize) -> HistogramCache {
+        HistogramCache {
+            capacity,
+            histograms: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    fn get(&mut self, nconst: &str) -> Option<SeparationHistogram> {
+        self.uses += 1;
+        let uses = self.uses;
+        self.histograms
+            .get_mut(nconst)
+            .map(|(histogram, last_used)| {
+                *last_used = uses;
+                histogram.clone()
+            })
+    }
+
+    fn insert(&mut self, histogram: SeparationHistogram) {
+        let nconst = histogram.center.nconst.clone();
+        if self.histograms.len() >= self.capacity && !self.histograms.contains_key(&nconst) {
+            // the cache is small, a scan finds the least recently used histogram
+            let oldest = self
+                .histograms
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(nconst, _)| nconst.clone());
+            if let Some(oldest) = oldest {
+                self.histograms.remove(&oldest);
+            }
+        }
+        self.uses += 1;
+        self.histograms.insert(nconst, (histogram, self.uses));
+    }
+
+    fn clear(&mut self) {
+        self.histograms.clear();
+    }
+}
+
+/// The title to principal mappings of the degrees of separation search, for the configured categories.
+/// Mappings are collected by `insert`, then `finish` turns them into the searchable graph.
 #[derive(Debug)]
 pub struct TitlePrincipalCache {
     insert_counter: AtomicUsize,
-    t_to_p: DashMap<String, HashSet<String>>,
-    p_to_t: DashMap<String, HashSet<String>>,
+    builder: Mutex<GraphBuilder>,
+    graph: RwLock<Arc<ActorGraph>>,
+    /// the recently used separation histograms of the current graph
+    histograms: Mutex<HistogramCache>,
 }
 
 impl TitlePrincipalCache {
     pub fn new() -> TitlePrincipalCache {
         TitlePrincipalCache {
             insert_counter: AtomicUsize::new(0),
-            t_to_p: DashMap::with_capacity(1_500_000),
-            p_to_t: DashMap::with_capacity(1_500_000),
+            builder: Mutex::new(GraphBuilder::new()),
+            graph: RwLock::new(Arc::new(ActorGraph::default())),
+            histograms: Mutex::new(HistogramCache::new(MAX_HISTOGRAMS)),
         }
     }
 
-    pub fn insert(&self, tconst: String, nconst: String) {
-        let mut entry = self.t_to_p.get_mut(&tconst);
-        if let Some(set) = entry.as_deref_mut() {
-            set.insert(nconst.clone());
-        } else {
-            let set: HashSet<String> = HashSet::from([nconst.clone()]);
-            self.t_to_p.insert(tconst.clone(), set);
-        }
-
-        let mut entry = self.p_to_t.get_mut(&nconst);
-        if let Some(set) = entry.as_deref_mut() {
-            set.insert(tconst);
-        } else {
-            let set: HashSet<String> = HashSet::from([tconst]);
-            self.p_to_t.insert(nconst, set);
+    pub fn insert(&self, tconst: &str, nconst: &str, category: &str) {
+        if !self.builder.lock().unwrap().add(tconst, nconst, category) {
+            println!(
+                "Ignoring invalid mapping {} - {} ({})",
+                tconst, nconst, category
+            );
         }
 
         let cntr = self.insert_counter.fetch_add(1, Ordering::Relaxed);
-        if cntr % 100000 == 0 {
+        if cntr.is_multiple_of(100000) {
             println!("Processed {} mappings", cntr);
         }
     }
 
-    pub fn t_to_p(&self, tconst: &str) -> Option<Ref<'_, String, HashSet<String>>> {
-        self.t_to_p.get(tconst)
+    /// Register the loaded categories in the order they are configured
+    pub fn insert_categories(&self, categories: &[String]) {
+        let mut builder = self.builder.lock().unwrap();
+        for category in categories {
+            if !builder.add_category(category) {
+                println!("Ignoring category {}", category);
+            }
+        }
+    }
+
+    /// Add the attributes of a title, for filtering searches
+    pub fn insert_title(&self, title: &TitleDetails) {
+        self.builder.lock().unwrap().add_title(
+            &title.tconst,
+            title.titletype.as_deref(),
+            title.startyear,
+            title.genres.as_deref(),
+            title.numvotes,
+            title.averagerating,
+        );
+    }
+
+    /// Build the graph from the inserted mappings, replacing the current one
+    pub fn finish(&self) {
+        let builder = std::mem::take(&mut *self.builder.lock().unwrap());
+        self.set_graph(builder.build());
+    }
+
+    pub fn set_graph(&self, graph: ActorGraph) {
+        let mut current = self.graph.write().unwrap();
+        *current = Arc::new(graph);
+        self.histograms.lock().unwrap().clear();
     }
 
-    pub fn p_to_t(&self, nconst: &str) -> Option<Ref<'_, String, HashSet<String>>> {
-        self.p_to_t.get(nconst)
+    /// The current graph, which stays valid even if the cache is reloaded meanwhile
+    pub fn graph(&self) -> Arc<ActorGraph> {
+        self.graph.read().unwrap().clone()
+    }
+
+    pub fn histogram(&self, nconst: &str) -> Option<SeparationHistogram> {
+        self.histograms.lock().unwrap().get(nconst)
+    }
+
+    /// Keep a histogram computed on `graph`, unless the graph was replaced meanwhile
+    pub fn set_histogram(&self, graph: &Arc<ActorGraph>, histogram: SeparationHistogram) {
+        let current = self.graph.read().unwrap();
+        if Arc::ptr_eq(&current, graph) {
+            self.histograms.lock().unwrap().insert(histogram);
+        }
     }
 
     pub fn len(&self) -> (usize, usize) {
-        (self.t_to_p.len(), self.p_to_t.len())
+        let graph = self.graph();
+        (graph.title_count(), graph.name_count())
+    }
+
+    /// The number of titles of a name
+    pub fn roles(&self, nconst: &str) -> usize {
+        let graph = self.graph();
+        graph
+            .name_id(nconst)
+            .map_or(0, |name| graph.titles_of(name).len())
     }
 
     pub fn ref_count(&self, name_basics: &NameBasics) -> usize {
-        self.p_to_t.get(&name_basics.nconst).map_or(0, |x| x.len())
+        self.roles(&name_basics.nconst)
     }
 }
 
@@ -278,7 +1009,9 @@ impl TitlePrincipalCache {
 mod tests {
     use std::collections::HashMap;
 
-    use crate::schemas::{DbRow, TitleDetails, TitlePrincipal};
+    use crate::schemas::{
+        DbRow, HistogramCache, NameBasics, SeparationHistogram, TitleDetails, TitlePrincipal,
+    };
 
     struct TestDbRow<'r> {
         map: HashMap<&'static str, &'r str>,
@@ -344,7 +1077,12 @@ mod tests {
             runtimeminutes: Some(RUNTIMEMINUTES),
             genres: Some(GENRES.to_string()),
             isadult: ISADULT,
+            relevance: None,
+            aka: None,
             principals: vec![],
+            crew: None,
+            akas: None,
+            episode: None,
         };
 
         let startyear = STARTYEAR.to_string();
@@ -361,7 +1099,7 @@ mod tests {
             ("numvotes", &numvotes),
             ("startyear", &startyear),
             ("runtimeminutes", &runtimeminutes),
-            ("genres", &GENRES),
+            ("genres", GENRES),
             ("isadult", &isadult),
         ]);
         let row = TestDbRow { map };
@@ -390,7 +1128,12 @@ mod tests {
             runtimeminutes: None,
             genres: None,
             isadult: false,
+            relevance: None,
+            aka: None,
             principals: vec![],
+            crew: None,
+            akas: None,
+            episode: None,
         };
 
         let map: HashMap<&'static str, &str> = HashMap::from([("tconst", TCONST)]);
@@ -403,7 +1146,7 @@ mod tests {
         assert!(new_title_basics.originaltitle.is_none());
         assert!(new_title_basics.startyear.is_none());
         assert!(new_title_basics.runtimeminutes.is_none());
-        assert!(new_title_basics.isadult == false);
+        assert!(!new_title_basics.isadult);
         assert!(new_title_basics.principals.is_empty());
     }
 
@@ -461,4 +1204,40 @@ mod tests {
         assert!(new_title_principal.birthyear.is_none());
         assert!(new_title_principal.deathyear.is_none());
     }
+
+    #[test]
+    fn name_basics_known_titles_are_opaque_ids() {
+        // ids are bound as query parameters, so quotes and SQL fragments are kept as they are
+        let crafted = "tt0000001,tt0000002') OR '1'='1,  , tt0000003'; DROP TABLE title_basics; --";
+        let map: HashMap<&'static str, &str> =
+            HashMap::from([("nconst", NCONST), ("knownfortitles", crafted)]);
+        let name_basics = NameBasics::from_db_row(&TestDbRow { map });
+        assert_eq!(
+            name_basics.title_ids(),
+            vec![
+                "tt0000001",
+                "tt0000002') OR '1'='1",
+                "tt0000003'; DROP TABLE title_basics; --"
+            ]
+        );
+
+        let map: HashMap<&'static str, &str> = HashMap::from([("nconst", NCONST)]);
+        assert!(NameBasics::from_db_row(&TestDbRow { map })
+            .title_ids()
+            .is_empty());
+    }
+
+    #[test]
+    fn histogram_cache_drops_least_recently_used() {
+        let histogram =
+            |nconst: &str| SeparationHistogram::new(nconst, vec![1], &[], &HashMap::new());
+        let mut cache = HistogramCache::new(2);
+        cache.insert(histogram("nm0000001"));
+        cache.insert(histogram("nm0000002"));
+        assert!(cache.get("nm0000001").is_some());
+        cache.insert(histogram("nm0000003"));
+        assert!(cache.get("nm0000002").is_none());
+        assert!(cache.get("nm0000001").is_some());
+        assert!(cache.get("nm0000003").is_some());
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Titles at least this similar to the search fragment match, like the `%` operator of pg_trgm
+pub const SIMILARITY_THRESHOLD: f64 = 0.3;
+
+/// Relevance added when all words of the fragment are title words, like a `simple` full-text match
+const WORD_MATCH_BOOST: f64 = 0.5;
+
+/// Relevance added when the fragment is the whole title
+const EXACT_MATCH_BOOST: f64 = 1.0;
+
+/// The lower-case alphanumeric words of a text
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+/// The trigrams of the words of a text, each word padded with two spaces in front and one behind
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let mut trigrams = HashSet::new();
+    for word in words(text) {
+        let padded: Vec<char> = "  ".chars().chain(word.chars()).chain([' ']).collect();
+        for trigram in padded.windows(3) {
+            trigrams.insert([trigram[0], trigram[1], trigram[2]]);
+        }
+    }
+    trigrams
+}
+
+/// The share of trigrams two texts have in common, as computed by pg_trgm's `similarity`
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let common = a.intersection(&b).count();
+    let all = a.len() + b.len() - common;
+    if all == 0 {
+        0.0
+    } else {
+        common as f64 / all as f64
+    }
+}
+
+/// Letters from the most to the least frequent in English, to estimate how many titles contain a trigram
+const LETTER_FREQUENCY: &str = "etaoinsrhldcumfpgwybvkxjqz";
+
+/// The `max` rarest trigrams of the fragment words, without padding, for a `LIKE` prefilter of candidate titles.
+/// Words shorter than a trigram are left out, they are in too many titles to narrow the candidates.
+pub fn inner_trigrams(fragment: &str, max: usize) -> Vec<String> {
+    let commonness = |trigram: &[char]| -> usize {
+        trigram
+            .iter()
+            .filter_map(|c| LETTER_FREQUENCY.find(*c))
+            .map(|rank| LETTER_FREQUENCY.len() - rank)
+            .sum()
+    };
+    let mut trigrams: Vec<(usize, String)> = Vec::new();
+    for word in words(fragment) {
+        let chars: Vec<char> = word.chars().collect();
+        trigrams.extend(
+            chars
+                .windows(3)
+                .map(|t| (commonness(t), t.iter().collect::<String>())),
+        );
+    }
+    trigrams.sort();
+    trigrams.dedup();
+    trigrams.truncate(max);
+    trigrams.into_iter().map(|(_, trigram)| trigram).collect()
+}
+
+/// The relevance of a title for a fuzzy search, `None` if the title does not match the fragment.
+/// The trigram similarity of the closest title, plus 0.5 if all fragment words are in the titles,
+/// plus 1 if the fragment is a whole title, to 4 decimals. Matches the score of the PostgreSQL fuzzy search.
+pub fn title_relevance(
+    fragment: &str,
+    primary: Option<&str>,
+    original: Option<&str>,
+) -> Option<f64> {
+    let titles: Vec<&str> = primary.into_iter().chain(original).collect();
+    let closest = titles
+        .iter()
+        .map(|title| similarity(title, fragment))
+        .fold(0.0, f64::max);
+
+    let title_words: HashSet<String> = titles.iter().flat_map(|title| words(title)).collect();
+    let mut fragment_words = words(fragment).peekable();
+    let word_match =
+        fragment_words.peek().is_some() && fragment_words.all(|word| title_words.contains(&word));
+
+    if closest < SIMILARITY_THRESHOLD && !word_match {
+        return None;
+    }
+    let mut relevance = closest;
+    if word_match {
+        relevance += WORD_MATCH_BOOST;
+    }
+    if titles
+        .iter()
+        .any(|title| title.to_lowercase() == fragment.to_lowercase())
+    {
+        relevance += EXACT_MATCH_BOOST;
+    }
+    // rounded like the PostgreSQL score, which adds up single precision similarities
+    Some((relevance * 10_000.0).round() / 10_000.0)
+}
+
+/// The form names are matched in: without accents, in lower case, with single spaces between words.
+/// Like the `imdb_searchname` function of the PostgreSQL schema, which uses `unaccent`.
+pub fn search_name(name: &str) -> String {
+    let mut unaccented = String::with_capacity(name.len());
+    // letters that do not decompose to a letter and an accent, as unaccent translates them
+    for c in name.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ß' => unaccented.push_str("ss"),
+            'Æ' | 'æ' => unaccented.push_str("ae"),
+            'Œ' | 'œ' => unaccented.push_str("oe"),
+            'Þ' | 'þ' => unaccented.push_str("th"),
+            'Ø' | 'ø' => unaccented.push('o'),
+            'Ł' | 'ł' => unaccented.push('l'),
+            'Đ' | 'đ' | 'Ð' | 'ð' => unaccented.push('d'),
+            'ı' => unaccented.push('i'),
+            c => unaccented.push(c),
+        }
+    }
+    unaccented
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::similarity::{inner_trigrams, search_name, similarity, title_relevance};
+
+    #[test]
+    fn trigram_similarity() {
+        // the values of pg_trgm
+        assert_eq!(similarity("alien", "Aliens"), 0.625);
+        assert!((similarity("word", "two words") - 0.363636).abs() < 1e-6);
+        assert_eq!(similarity("Alien", "alien"), 1.0);
+        assert_eq!(similarity("", "alien"), 0.0);
+        assert_eq!(inner_trigrams("Up Alien", 8), vec!["ali", "lie", "ien"]);
+        assert_eq!(inner_trigrams("the quiz", 2), vec!["uiz", "qui"]);
+        assert!(inner_trigrams("Up", 8).is_empty());
+    }
+
+    #[test]
+    fn exact_titles_first() {
+        let exact = title_relevance("Alien", Some("Alien"), Some("Alien")).unwrap();
+        let sequel = title_relevance("Alien", Some("Aliens"), None).unwrap();
+        let word = title_relevance("alien", Some("Alien: Covenant"), None).unwrap();
+        assert!(exact > word && word > sequel);
+        assert!(title_relevance("Forest Gump", Some("Forrest Gump"), None).unwrap() > 0.7);
+        assert!(title_relevance("Alien", Some("Footloose"), None).is_none());
+    }
+
+    #[test]
+    fn search_names() {
+        assert_eq!(search_name("Penélope Cruz"), "penelope cruz");
+        assert_eq!(search_name("  tom   HANKS "), "tom hanks");
+        assert_eq!(search_name("Zoë Kravitz"), search_name("zoe kravitz"));
+        assert_eq!(
+            search_name("Mads Mikkelsen Ø Łukasz"),
+            "mads mikkelsen o lukasz"
+        );
+        assert_eq!(search_name("Günther Maria Halmer"), "gunther maria halmer");
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::graph::ActorGraph;
+
+const MAGIC: &[u8; 8] = b"IMDBGRPH";
+
+/// Bump when the layout of `ActorGraph` changes, older snapshots are then ignored
+const FORMAT_VERSION: u32 = 4;
+
+/// Write the graph to `path`, tagged with the fingerprint of the dataset it was loaded from.
+/// The file is written next to its final location and renamed, so readers never see a partial snapshot.
+pub fn save(path: &Path, fingerprint: &str, graph: &ActorGraph) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut w = BufWriter::with_capacity(1 << 20, File::create(&tmp_path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        w.write_all(&(fingerprint.len() as u32).to_le_bytes())?;
+        w.write_all(fingerprint.as_bytes())?;
+        graph.write_to(&mut w)?;
+        w.flush()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+/// Read the graph from `path`. Returns `None` if there is no snapshot, or it was written
+/// by another format version or for another dataset.
+pub fn load(path: &Path, fingerprint: &str) -> io::Result<Option<ActorGraph>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut r = BufReader::with_capacity(1 << 20, file);
+
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a graph snapshot",
+        ));
+    }
+    if read_u32(&mut r)? != FORMAT_VERSION {
+        return Ok(None);
+    }
+    let mut snapshot_fingerprint = vec![0u8; read_u32(&mut r)? as usize];
+    r.read_exact(&mut snapshot_fingerprint)?;
+    if snapshot_fingerprint != fingerprint.as_bytes() {
+        return Ok(None);
+    }
+
+    ActorGraph::read_from(&mut r).map(Some)
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::graph::GraphBuilder;
+    use crate::snapshot::{load, save};
+
+    #[test]
+    fn save_and_load() {
+        let mut builder = GraphBuilder::new();
+        builder.add("tt0000001", "nm0000001", "actor");
+        builder.add("tt0000001", "nm0000002", "actor");
+        let graph = builder.build();
+
+        let path = std::env::temp_dir().join(format!("imdbcopy-{}.snapshot", std::process::id()));
+        save(&path, "import:1", &graph).unwrap();
+
+        let loaded = load(&path, "import:1").unwrap().unwrap();
+        assert_eq!(loaded.name_count(), 2);
+        assert!(load(&path, "import:2").unwrap().is_none());
+
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path, "import:1").unwrap().is_none());
+    }
+}
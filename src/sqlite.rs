@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::future;
+
+use rocket::futures::StreamExt;
+use rocket::serde::json;
+use rocket_db_pools::sqlx::{self, Row};
+
+use crate::error::ApiError;
+use crate::repo::{with_known_titles, MovieRepository};
+use crate::schemas::{
+    DbRow, Episode, EpisodeSeries, Filmography, FilmographyTitle, NameBasics, SearchMode,
+    SeriesEpisodes, TitleAka, TitleBasics, TitleCrew, TitleDetails, TitlePrincipal,
+    TitlePrincipalCache, TitleQuery, TitleToNames,
+};
+use crate::similarity;
+
+/// The trigrams of a fuzzy search fragment that prefilter the candidate titles, the rarest ones.
+/// Every trigram is a bound variable and a `LIKE` scan, so long fragments only use some of them.
+const MAX_FUZZY_TRIGRAMS: usize = 8;
+
+/// The candidate titles, and alternate titles, scored by a fuzzy search
+const MAX_FUZZY_CANDIDATES: i64 = 10_000;
+
+/// Runs the queries of `PostgresRepository` on a SQLite database, for single-machine deployments.
+/// SQLite has no arrays, lists of ids are bound as JSON arrays and expanded with `json_each`.
+pub struct SqliteRepository {
+    db_pool: sqlx::SqlitePool,
+}
+
+impl SqliteRepository {
+    pub fn new(db_pool: sqlx::SqlitePool) -> SqliteRepository {
+        SqliteRepository { db_pool }
+    }
+
+    /// A page of the movies containing the fragment in a title or alternate title,
+    /// and the number of these movies
+    async fn titles_containing(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+        // LIKE ignores the case of ASCII letters, like ilike does
+        let title_name = query.fragment.as_str();
+        let title_match = format!("%{}%", title_name);
+        // the best match of each title: the primary or original title, else the first alternate title
+        let from_where = "FROM (
+            SELECT *, row_number() OVER (PARTITION BY tconst ORDER BY aka_title IS NOT NULL, aka_ordering) rn
+            FROM (
+                SELECT tb.tconst, NULL aka_title, NULL aka_region, NULL aka_language, 0 aka_ordering
+                FROM title_basics tb
+                WHERE tb.primarytitle LIKE ?1 OR tb.originaltitle LIKE ?1
+                UNION ALL
+                SELECT ta.titleid, ta.title, ta.region, ta.language, ta.ordering
+                FROM title_akas ta
+                WHERE ta.title LIKE ?1 AND (?2 IS NULL OR ta.region = ?2) AND (?3 IS NULL OR ta.language = ?3)
+            )
+        ) m
+        JOIN title_basics tb ON tb.tconst = m.tconst
+        JOIN title_ratings tr ON tr.tconst = tb.tconst
+        WHERE m.rn = 1 AND tb.titletype IN (SELECT value FROM json_each(?4))";
+
+        let sql = format!("SELECT count(*) total {}", from_where);
+        let title_types = json_array(&query.title_types);
+        let total: i64 = sqlx::query(&sql)
+            .bind(&title_match)
+            .bind(&query.region)
+            .bind(&query.language)
+            .bind(&title_types)
+            .fetch_one(&self.db_pool)
+            .await
+            .map(|r| r.get("total"))
+            .map_err(|err| {
+                ApiError::database(format!("Error counting titles named {}", title_name), err)
+            })?;
+        if total == 0 {
+            return Ok((vec![], 0));
+        }
+
+        let sql = format!(
+            "SELECT tb.*, tr.numvotes, tr.averagerating, m.aka_title, m.aka_region, m.aka_language
+            {} ORDER BY {} LIMIT ?5 OFFSET ?6",
+            from_where,
+            query.sort.order_by()
+        );
+        let title_vec = sqlx::query(&sql)
+            .bind(&title_match)
+            .bind(&query.region)
+            .bind(&query.language)
+            .bind(&title_types)
+            .bind(query.limit)
+            .bind(query.offset)
+            .fetch_all(&self.db_pool)
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .map(|r| TitleDetails::from_db_row(r))
+                    .collect::<Vec<TitleDetails>>()
+            })
+            .map_err(|err| {
+                ApiError::database(format!("Error querying title {} by name", title_name), err)
+            })?;
+        Ok((title_vec, total))
+    }
+
+    /// A page of the movies matching a fuzzy search, and the number of matching movies.
+    /// SQLite has no trigram index, the candidate titles and alternate titles share one of the rarest
+    /// trigrams of the fragment, and are scored like the memory repository scores them.
+    /// The most voted candidates are scored, so the total is a lower bound for fragments found in many titles.
+    async fn titles_similar_to(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+        let trigrams = similarity::inner_trigrams(&query.fragment, MAX_FUZZY_TRIGRAMS);
+        if trigrams.is_empty() {
+            return Err(ApiError::bad_input(
+                Some(&query.fragment),
+                "A fuzzy search needs a word of at least 3 characters".to_string(),
+            ));
+        }
+        let like_trigrams = |columns: &[&str]| {
+            (1..=trigrams.len())
+                .flat_map(|idx| {
+                    columns
+                        .iter()
+                        .map(move |column| format!("{column} LIKE ?{idx}"))
+                })
+                .collect::<Vec<String>>()
+                .join(" OR ")
+        };
+        let db_err = |err| {
+            ApiError::database(
+                format!("Error searching titles similar to {}", query.fragment),
+                err,
+            )
+        };
+
+        let sql = format!(
+            "SELECT ta.titleid, ta.title, ta.region, ta.language FROM title_akas ta
+            WHERE ({}) AND (?{n} IS NULL OR ta.region = ?{n}) AND (?{m} IS NULL OR ta.language = ?{m})
+            ORDER BY ta.titleid, ta.ordering LIMIT ?{l}",
+            like_trigrams(&["ta.title"]),
+            n = trigrams.len() + 1,
+            m = trigrams.len() + 2,
+            l = trigrams.len() + 3
+        );
+        let mut aka_query = sqlx::query(&sql);
+        for trigram in &trigrams {
+            aka_query = aka_query.bind(format!("%{}%", trigram));
+        }
+        let mut akas: HashMap<String, Vec<TitleAka>> = HashMap::new();
+        for r in aka_query
+            .bind(&query.region)
+            .bind(&query.language)
+            .bind(MAX_FUZZY_CANDIDATES)
+            .fetch_all(&self.db_pool)
+            .await
+            .map_err(db_err)?
+        {
+            akas.entry(r.string("titleid"))
+                .or_default()
+                .push(TitleAka::from_db_row(&r));
+        }
+
+        let sql = format!(
+            "SELECT tb.*, tr.numvotes, tr.averagerating FROM title_basics tb
+            JOIN title_ratings tr ON tr.tconst = tb.tconst
+            WHERE tb.titletype IN (SELECT value FROM json_each(?{n}))
+            AND ({} OR tb.tconst IN (SELECT value FROM json_each(?{m})))
+            ORDER BY tr.numvotes DESC LIMIT ?{l}",
+            like_trigrams(&["tb.primarytitle", "tb.originaltitle"]),
+            n = trigrams.len() + 1,
+            m = trigrams.len() + 2,
+            l = trigrams.len() + 3
+        );
+        let mut candidates = sqlx::query(&sql);
+        for trigram in &trigrams {
+            candidates = candidates.bind(format!("%{}%", trigram));
+        }
+        let aka_tconsts: Vec<String> = akas.keys().cloned().collect();
+        let mut titles: Vec<TitleDetails> = candidates
+            .bind(json_array(&query.title_types))
+            .bind(json_array(&aka_tconsts))
+            .bind(MAX_FUZZY_CANDIDATES)
+            .fetch_all(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .iter()
+            .filter_map(|r| {
+                let title_akas = akas
+                    .get(&r.string("tconst"))
+                    .map_or(&[][..], |a| a.as_slice());
+                TitleDetails::from_db_row(r).matching(query, title_akas)
+            })
+            .collect();
+        titles.sort_by(|a, b| query.sort.compare(a, b));
+
+        let total = titles.len() as i64;
+        let page = titles
+            .into_iter()
+            .skip(query.offset as usize)
+            .take(query.limit as usize)
+            .collect();
+        Ok((page, total))
+    }
+
+    /// The principals of the titles in billing order, by title id, retrieved in one query
+    async fn principals_by_titles(
+        &self,
+        title_ids: &[String],
+    ) -> Result<HashMap<String, Vec<TitlePrincipal>>, ApiError> {
+        let sql = "SELECT tp.tconst, tp.nconst, tp.category, tp.job, tp.characters, nb.primaryname, nb.birthyear, nb.deathyear
+        FROM title_principals tp
+        JOIN name_basics nb ON nb.nconst = tp.nconst
+        WHERE tp.tconst IN (SELECT value FROM json_each(?1))
+        ORDER BY tp.tconst, tp.ordering";
+        sqlx::query(sql)
+            .bind(json_array(title_ids))
+            .fetch_all(&self.db_pool)
+            .await
+            .map(|rows| {
+                let mut principals: HashMap<String, Vec<TitlePrincipal>> = HashMap::new();
+                for r in &rows {
+                    principals
+                        .entry(r.string("tconst"))
+                        .or_default()
+                        .push(TitlePrincipal::from_db_row(r));
+                }
+                principals
+            })
+            .map_err(|err| {
+                ApiError::database(
+                    format!(
+                        "Error retrieving title principals for title ids {:?}",
+                        title_ids
+                    ),
+                    err,
+                )
+            })
+    }
+}
+
+fn json_array(values: &[String]) -> String {
+    json::to_string(&values).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Translate a PostgreSQL `like` pattern to a `GLOB` pattern, because the SQLite `LIKE` ignores case
+fn like_to_glob(pattern: &str) -> String {
+    let mut glob = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        match c {
+            '%' => glob.push('*'),
+            '_' => glob.push('?'),
+            '*' | '?' | '[' => {
+                glob.push('[');
+                glob.push(c);
+                glob.push(']');
+            }
+            _ => glob.push(c),
+        }
+    }
+    glob
+}
+
+#[rocket::async_trait]
+impl MovieRepository for SqliteRepository {
+    async fn titles_by_name(
+        &self,
+        query: &TitleQuery,
+    ) -> Result<(Vec<TitleDetails>, i64), ApiError> {
+        let (mut title_vec, total) = match query.mode {
+            SearchMode::Substring => self.titles_containing(query).await?,
+            SearchMode::Fuzzy => self.titles_similar_to(query).await?,
+        };
+        if total == 0 {
+            return Err(ApiError::not_found(
+                &query.fragment,
+                format!("Could not find Title {}", query.fragment),
+            ));
+        }
+
+        let tconsts: Vec<String> = title_vec
+            .iter()
+            .map(|t| t.get_title_id().to_string())
+            .collect();
+        let mut principals = self.principals_by_titles(&tconsts).await?;
+        for t in title_vec.iter_mut() {
+            if let Some(title_principals) = principals.remove(t.get_title_id()) {
+                t.add_principals(&title_principals);
+            }
+        }
+        Ok((title_vec, total))
+    }
+
+    async fn title_details(&self, tconst: &str) -> Result<TitleDetails, ApiError> {
+        let db_err = |err| ApiError::database(format!("Error retrieving title {}", tconst), err);
+        let sql = "SELECT tb.*, tr.numvotes, tr.averagerating
+        FROM title_basics tb
+        LEFT JOIN title_ratings tr ON tr.tconst = tb.tconst
+        WHERE tb.tconst = ?1";
+        let mut title = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .map(|r| TitleDetails::from_db_row(&r))
+            .ok_or_else(|| {
+                ApiError::not_found(tconst, format!("Could not find Title {}", tconst))
+            })?;
+
+        let mut principals = self.principals_by_titles(&[tconst.to_string()]).await?;
+        title.add_principals(&principals.remove(tconst).unwrap_or_default());
+
+        let sql = "SELECT directors, writers FROM title_crew WHERE tconst = ?1";
+        let mut crew = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .map(|r| TitleCrew::from_db_row(&r))
+            .unwrap_or_default();
+        crew.set_names(&self.primary_names(&crew.name_ids()).await?);
+
+        let sql =
+            "SELECT title, region, language FROM title_akas WHERE titleid = ?1 ORDER BY ordering";
+        let akas = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_all(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .iter()
+            .map(|r| TitleAka::from_db_row(r))
+            .collect();
+
+        let sql = "SELECT te.parenttconst tconst, tb.titletype, tb.primarytitle, tb.startyear, te.seasonnumber, te.episodenumber
+        FROM title_episode te
+        LEFT JOIN title_basics tb ON tb.tconst = te.parenttconst
+        WHERE te.tconst = ?1";
+        let episode = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .map(|r| EpisodeSeries::from_db_row(&r));
+
+        title.add_details(crew, akas, episode);
+        Ok(title)
+    }
+
+    async fn basics_for_name(
+        &self,
+        cache: &TitlePrincipalCache,
+        name: &str,
+        use_wildcard: bool,
+    ) -> Result<Vec<NameBasics>, ApiError> {
+        let (where_clause, pattern) = if use_wildcard {
+            ("primaryname GLOB ?1", like_to_glob(name))
+        } else {
+            ("searchname = ?1", similarity::search_name(name))
+        };
+        let sql = format!(
+            "SELECT nconst, primaryname, primaryprofession, birthyear, deathyear, knownfortitles FROM name_basics WHERE {}",
+            where_clause
+        );
+        let name_vec = sqlx::query(&sql)
+            .bind(pattern)
+            .fetch_all(&self.db_pool)
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .map(|r| NameBasics::from_db_row(r))
+                    .collect::<Vec<NameBasics>>()
+            })
+            .map_err(|err| ApiError::database(format!("Error searching for name {}", name), err))?;
+
+        let tconsts: Vec<String> = name_vec.iter().flat_map(|n| n.title_ids()).collect();
+        let known_titles = self.title_basics(&tconsts).await?;
+        Ok(with_known_titles(cache, name_vec, &known_titles))
+    }
+
+    async fn primary_names(&self, nconsts: &[String]) -> Result<HashMap<String, String>, ApiError> {
+        let sql = "SELECT nconst, primaryname FROM name_basics WHERE nconst IN (SELECT value FROM json_each(?1))";
+        sqlx::query(sql)
+            .bind(json_array(nconsts))
+            .fetch_all(&self.db_pool)
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .filter_map(|r| Some((r.string("nconst"), r.opt_string("primaryname")?)))
+                    .collect()
+            })
+            .map_err(|err| ApiError::database("Error retrieving names".to_string(), err))
+    }
+
+    async fn title_basics(
+        &self,
+        tconsts: &[String],
+    ) -> Result<HashMap<String, TitleBasics>, ApiError> {
+        let sql = "SELECT tconst, titletype, primarytitle, startyear FROM title_basics WHERE tconst IN (SELECT value FROM json_each(?1))";
+        sqlx::query(sql)
+            .bind(json_array(tconsts))
+            .fetch_all(&self.db_pool)
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .map(|r| (r.string("tconst"), TitleBasics::from_db_row(r)))
+                    .collect()
+            })
+            .map_err(|err| ApiError::database("Error retrieving titles".to_string(), err))
+    }
+
+    async fn filmography(
+        &self,
+        nconst: &str,
+        title_types: Option<&[String]>,
+    ) -> Result<Filmography, ApiError> {
+        let db_err = |err| {
+            ApiError::database(
+                format!("Error retrieving the filmography of {}", nconst),
+                err,
+            )
+        };
+        let sql = "SELECT nconst, primaryname, primaryprofession, birthyear, deathyear FROM name_basics WHERE nconst = ?1";
+        let name = sqlx::query(sql)
+            .bind(nconst)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| {
+                ApiError::not_found(nconst, format!("Could not find Name {}", nconst))
+            })?;
+
+        let sql = "SELECT tp.category, tp.job, tp.characters, tb.tconst, tb.titletype, tb.primarytitle, tb.startyear, tr.averagerating, tr.numvotes
+        FROM title_principals tp
+        JOIN title_basics tb ON tb.tconst = tp.tconst
+        LEFT JOIN title_ratings tr ON tr.tconst = tp.tconst
+        WHERE tp.nconst = ?1 AND (?2 IS NULL OR tb.titletype IN (SELECT value FROM json_each(?2)))";
+        let titles = sqlx::query(sql)
+            .bind(nconst)
+            .bind(title_types.map(json_array))
+            .fetch_all(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .iter()
+            .map(|r| (r.string("category"), FilmographyTitle::from_db_row(r)))
+            .collect();
+        Ok(Filmography::new(&name, titles))
+    }
+
+    async fn title_to_names(
+        &self,
+        tconst: &str,
+        nconst1: &str,
+        nconst2: &str,
+    ) -> Result<TitleToNames, ApiError> {
+        let sql = "SELECT tb.tconst, tb.primarytitle, tb.startyear, tb.titletype, tp1.nconst nconst1, tp1.characters characters1, nb1.primaryname primaryname1, tp2.nconst nconst2, tp2.characters characters2, nb2.primaryname primaryname2
+        FROM title_basics tb
+        JOIN title_principals tp1 ON tp1.tconst = tb.tconst AND tp1.nconst = ?1
+        JOIN title_principals tp2 ON tp2.tconst = tb.tconst AND tp2.nconst = ?2
+        JOIN name_basics nb1 ON nb1.nconst = tp1.nconst
+        JOIN name_basics nb2 ON nb2.nconst = tp2.nconst
+        WHERE tb.tconst = ?3";
+        sqlx::query(sql)
+            .bind(nconst1)
+            .bind(nconst2)
+            .bind(tconst)
+            .fetch_one(&self.db_pool)
+            .await
+            .map(|r| TitleToNames::from_db_row(&r))
+            .map_err(|err| {
+                ApiError::database(
+                    format!(
+                        "Error retrieving title {} and principals {}, {}",
+                        tconst, nconst1, nconst2
+                    ),
+                    err,
+                )
+            })
+    }
+
+    async fn series_episodes(&self, tconst: &str) -> Result<SeriesEpisodes, ApiError> {
+        let db_err =
+            |err| ApiError::database(format!("Error retrieving the episodes of {}", tconst), err);
+        let sql =
+            "SELECT tconst, titletype, primarytitle, startyear FROM title_basics WHERE tconst = ?1";
+        let series = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .map(|r| TitleBasics::from_db_row(&r))
+            .ok_or_else(|| {
+                ApiError::not_found(tconst, format!("Could not find Title {}", tconst))
+            })?;
+
+        let sql = "SELECT te.tconst, te.seasonnumber, te.episodenumber, tb.primarytitle, tb.startyear, tr.averagerating, tr.numvotes
+        FROM title_episode te
+        LEFT JOIN title_basics tb ON tb.tconst = te.tconst
+        LEFT JOIN title_ratings tr ON tr.tconst = te.tconst
+        WHERE te.parenttconst = ?1";
+        let episodes = sqlx::query(sql)
+            .bind(tconst)
+            .fetch_all(&self.db_pool)
+            .await
+            .map_err(db_err)?
+            .iter()
+            .map(|r| (r.opt_i32("seasonnumber"), Episode::from_db_row(r)))
+            .collect();
+        Ok(SeriesEpisodes::new(series, episodes))
+    }
+
+    async fn dataset_fingerprint(&self) -> Result<Option<String>, ApiError> {
+        let db_err = |err| ApiError::database("Error identifying the dataset".to_string(), err);
+        let sql = "SELECT id, imported_at FROM dataset_imports ORDER BY id DESC LIMIT 1";
+        let last_import = sqlx::query(sql)
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(db_err)?;
+        if let Some(r) = last_import {
+            Ok(Some(format!(
+                "sqlite:import:{}:{}",
+                r.i32("id"),
+                r.string("imported_at")
+            )))
+        } else {
+            let sql = "SELECT count(*) cnt FROM title_principals";
+            let r = sqlx::query(sql)
+                .fetch_one(&self.db_pool)
+                .await
+                .map_err(db_err)?;
+            Ok(Some(format!("sqlite:rows:{}", r.i32("cnt"))))
+        }
+    }
+
+    async fn titles_to_principals(&self, cache: &TitlePrincipalCache, categories: &[String]) {
+        cache.insert_categories(categories);
+        let categories = json_array(categories);
+        let sql = "SELECT tconst, nconst, category FROM title_principals WHERE category IN (SELECT value FROM json_each(?1))";
+        sqlx::query(sql)
+            .bind(&categories)
+            .fetch(&self.db_pool)
+            .for_each(|result| {
+                match result {
+                    Ok(row) => {
+                        let tconst = row.get::<&str, usize>(0);
+                        let nconst = row.get::<&str, usize>(1);
+                        let category = row.get::<&str, usize>(2);
+                        cache.insert(tconst, nconst, category);
+                    }
+                    Err(error) => {
+                        println!("Error reading DB row: {}", error);
+                    }
+                }
+                future::ready(())
+            })
+            .await;
+
+        let sql = "SELECT tb.tconst, tb.titletype, tb.startyear, tb.genres, tr.numvotes, tr.averagerating
+        FROM title_basics tb
+        LEFT JOIN title_ratings tr ON tr.tconst = tb.tconst
+        WHERE EXISTS (SELECT 1 FROM title_principals tp WHERE tp.tconst = tb.tconst AND tp.category IN (SELECT value FROM json_each(?1)))";
+        sqlx::query(sql)
+            .bind(&categories)
+            .fetch(&self.db_pool)
+            .for_each(|result| {
+                match result {
+                    Ok(row) => cache.insert_title(&TitleDetails::from_db_row(&row)),
+                    Err(error) => {
+                        println!("Error reading DB row: {}", error);
+                    }
+                }
+                future::ready(())
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rocket::serde::json;
+    use rocket_db_pools::sqlx;
+
+    use crate::repo::MovieRepository;
+    use crate::schemas::{SearchMode, TitlePrincipalCache, TitleQuery, TitleSort};
+    use crate::sqlite::{like_to_glob, SqliteRepository};
+    use crate::{import, migrate};
+
+    #[rocket::async_test]
+    async fn fixtures_from_sqlite() {
+        let path = std::env::temp_dir().join(format!("imdbcopy-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db_pool = sqlx::SqlitePool::connect(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .unwrap();
+        migrate::run_sqlite(&db_pool).await.unwrap();
+        import::import_dir_sqlite(&db_pool, Path::new("fixtures"))
+            .await
+            .unwrap();
+        let repository = SqliteRepository::new(db_pool);
+
+        let (titles, total) = repository
+            .titles_by_name(&TitleQuery {
+                fragment: "FOOT".to_string(),
+                mode: SearchMode::Substring,
+                region: None,
+                language: None,
+                title_types: vec!["movie".to_string()],
+                sort: TitleSort::Year,
+                limit: 10,
+                offset: 0,
+            })
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(titles[0].get_title_id(), "tt0087277");
+
+        let fuzzy = |fragment: &str| TitleQuery {
+            fragment: fragment.to_string(),
+            mode: SearchMode::Fuzzy,
+            region: None,
+            language: None,
+            title_types: vec!["movie".to_string()],
+            sort: TitleSort::Relevance,
+            limit: 10,
+            offset: 0,
+        };
+        let (titles, _) = repository
+            .titles_by_name(&fuzzy("forest gump"))
+            .await
+            .unwrap();
+        assert_eq!(titles[0].get_title_id(), "tt0109830");
+        assert!(repository.titles_by_name(&fuzzy("of it")).await.is_err());
+
+        let title = repository.title_details("tt0583459").await.unwrap();
+        let title = json::to_string(&title).unwrap();
+        assert!(title.contains(r#""directors":[{"nconst":"nm0156343","primaryname":null}]"#));
+        assert!(title.contains(r#""seasonnumber":1,"episodenumber":9"#));
+
+        let cache = TitlePrincipalCache::new();
+        let names = repository
+            .basics_for_name(&cache, "Kevin%", true)
+            .await
+            .unwrap();
+        assert_eq!(names.len(), 1);
+        let names = repository
+            .basics_for_name(&cache, "PENELOPE  Cruz", false)
+            .await
+            .unwrap();
+        assert_eq!(names[0].get_name_id(), "nm0004851");
+        assert!(repository
+            .basics_for_name(&cache, "kevin%", true)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let step = repository
+            .title_to_names("tt0112384", "nm0000158", "nm0000102")
+            .await
+            .unwrap();
+        let step = json::to_string(&step).unwrap();
+        assert!(step.contains(r#""primaryname":"Kevin Bacon","characters":"[\"Jack Swigert\"]""#));
+        assert!(repository
+            .dataset_fingerprint()
+            .await
+            .unwrap()
+            .unwrap()
+            .starts_with("sqlite:import:1:"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn like_patterns_as_globs() {
+        assert_eq!(like_to_glob("Kevin%"), "Kevin*");
+        assert_eq!(like_to_glob("K_vin Bacon"), "K?vin Bacon");
+        assert_eq!(like_to_glob("Who? [*]"), "Who[?] [[][*]]");
+    }
+}